@@ -0,0 +1,573 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek},
+    os::unix::{self, fs::MetadataExt},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use filetime::FileTime;
+use serde::Serialize;
+
+use crate::{
+    database::FileKind,
+    error::{Error, Result, WithPath},
+    files::{is_contained_link, is_relative},
+    filetransfer::set_metadata,
+    tar::{Encoding, Tar},
+    zip::Zip,
+};
+
+/// Formats a package can be built in. The format of an existing package is
+/// detected from its first bytes, so its file name does not matter.
+#[derive(Serialize, Clone, Copy, PartialEq, Default)]
+pub enum PackageFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "tar.xz")]
+    TarXz,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+}
+
+impl PackageFormat {
+    pub const ALL: [PackageFormat; 4] = [
+        PackageFormat::Zip,
+        PackageFormat::TarGz,
+        PackageFormat::TarXz,
+        PackageFormat::TarZst,
+    ];
+
+    /// Extension of packages in this format, following `.ebpm.`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            PackageFormat::Zip => "zip",
+            PackageFormat::TarGz => "tar.gz",
+            PackageFormat::TarXz => "tar.xz",
+            PackageFormat::TarZst => "tar.zst",
+        }
+    }
+
+    /// Whether `path` is named like a package, `<name>.ebpm.<extension>`.
+    pub fn is_package_name(path: &Path) -> bool {
+        let name = path.to_string_lossy();
+        Self::ALL
+            .iter()
+            .any(|it| name.ends_with(&format!(".ebpm.{}", it.extension())))
+    }
+
+    /// Detects the format of the package `archive` from the magic bytes at the
+    /// start of `source`, which is rewound afterwards.
+    pub fn detect(archive: &Path, source: &mut (impl Read + Seek)) -> Result<Self> {
+        let mut magic = Vec::new();
+        source
+            .rewind()
+            .and_then(|_| source.take(6).read_to_end(&mut magic))
+            .and_then(|_| source.rewind())
+            .with_path(archive)?;
+
+        match magic.as_slice() {
+            [b'P', b'K', 3, 4, ..] | [b'P', b'K', 5, 6, ..] => Ok(PackageFormat::Zip),
+            [0x1f, 0x8b, ..] => Ok(PackageFormat::TarGz),
+            [0xfd, b'7', b'z', b'X', b'Z', 0] => Ok(PackageFormat::TarXz),
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Ok(PackageFormat::TarZst),
+            _ => Err(Error::archive(archive, "unknown archive format")),
+        }
+    }
+
+    fn backend(self) -> Box<dyn Format> {
+        match self {
+            PackageFormat::Zip => Box::new(Zip),
+            PackageFormat::TarGz => Box::new(Tar(Encoding::Gzip)),
+            PackageFormat::TarXz => Box::new(Tar(Encoding::Xz)),
+            PackageFormat::TarZst => Box::new(Tar(Encoding::Zstd)),
+        }
+    }
+}
+
+impl Display for PackageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl TryFrom<&str> for PackageFormat {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|it| it.extension() == value)
+            .ok_or_else(|| {
+                Error::Usage(format!(
+                    "'{}' is not a valid package format, expected 'zip', 'tar.gz', 'tar.xz' or 'tar.zst'",
+                    value
+                ))
+            })
+    }
+}
+
+/// Compression methods for the entries of zip packages. Tar packages are
+/// compressed as a whole by their format.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Compression {
+    Store,
+    Deflate,
+    Bzip2,
+    Zstd,
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Compression::Store => "store",
+            Compression::Deflate => "deflate",
+            Compression::Bzip2 => "bzip2",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
+
+impl TryFrom<&str> for Compression {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "store" => Ok(Compression::Store),
+            "deflate" => Ok(Compression::Deflate),
+            "bzip2" => Ok(Compression::Bzip2),
+            "zstd" => Ok(Compression::Zstd),
+            other => Err(Error::Usage(format!(
+                "'{}' is not a valid compression, expected 'store', 'deflate', 'bzip2' or 'zstd'",
+                other
+            ))),
+        }
+    }
+}
+
+/// How `ebpm build` writes a package.
+#[derive(Clone, Copy, Default)]
+pub struct BuildOptions {
+    pub format: PackageFormat,
+    /// Compression of zip entries, Bzip2 when not set.
+    pub compression: Option<Compression>,
+    /// Compression level, the default of the compression when not set.
+    pub level: Option<i64>,
+    /// Modification time of every entry of a reproducible build. Such builds
+    /// also sort the entries, drop ownership and use 755 or 644 as modes.
+    pub reproducible: Option<i64>,
+}
+
+impl BuildOptions {
+    /// Timestamp of reproducible builds: `SOURCE_DATE_EPOCH` when set, and
+    /// otherwise 1980-01-01, the earliest time a zip entry can hold.
+    pub fn source_date_epoch() -> Result<i64> {
+        match env::var("SOURCE_DATE_EPOCH") {
+            Ok(value) => value.trim().parse().map_err(|_| {
+                Error::Usage(format!(
+                    "SOURCE_DATE_EPOCH must be a unix timestamp, not '{}'",
+                    value
+                ))
+            }),
+            Err(_) => Ok(315_532_800),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let compression = self.compression.unwrap_or(Compression::Bzip2);
+        if self.compression.is_some() && self.format != PackageFormat::Zip {
+            return Err(Error::Usage(format!(
+                "{} packages cannot be built with {} compression",
+                self.format, compression
+            )));
+        }
+
+        let Some(level) = self.level else {
+            return Ok(());
+        };
+        let levels = match (self.format, compression) {
+            (PackageFormat::Zip, Compression::Store) => None,
+            (PackageFormat::Zip, Compression::Bzip2) => Some(1..=9),
+            (PackageFormat::Zip, Compression::Zstd) | (PackageFormat::TarZst, _) => Some(1..=22),
+            _ => Some(0..=9),
+        };
+        match levels {
+            Some(levels) if levels.contains(&level) => Ok(()),
+            Some(levels) => Err(Error::Usage(format!(
+                "Compression level {} is out of range {}..={}",
+                level,
+                levels.start(),
+                levels.end()
+            ))),
+            None => Err(Error::Usage(
+                "Stored packages have no compression level".into(),
+            )),
+        }
+    }
+}
+
+/// A seekable source of a package.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Reading and writing of one archive format. Sources are read from their
+/// current position, `archive` only names the package in errors.
+pub(crate) trait Format {
+    fn compress(&self, archive: &Path, entries: &[Entry], options: &BuildOptions) -> Result<()>;
+
+    fn read_file(&self, archive: &Path, source: &mut dyn ReadSeek, name: &str) -> Result<String>;
+
+    /// Extracts every entry with the help of an [`Extraction`].
+    fn extract(&self, archive: &Path, source: &mut dyn ReadSeek, destination: &Path) -> Result<()>;
+
+    fn list(&self, archive: &Path, source: &mut dyn ReadSeek) -> Result<Vec<ArchiveEntry>>;
+}
+
+/// An entry as stored in a package, see [`Extractor::list`].
+#[derive(Serialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    /// Not set for entry types that are never extracted, such as devices or hard links.
+    pub kind: Option<FileKind>,
+    pub mode: Option<u32>,
+    pub size: u64,
+    /// Size within the archive, for formats compressing every entry on its own.
+    pub compressed_size: Option<u64>,
+    /// Target of symlinks and hard links.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+/// An entry of an archive. Files given by path may also be directories,
+/// which are then archived recursively, or symlinks, which are archived as
+/// symlinks.
+pub enum ArchivedFile<'a> {
+    FileWithNewName(&'a String, &'a String),
+    Data(&'a str, &'a [u8]),
+    /// A directory entry for the directory at the path, without its contents.
+    Dir(&'a String, &'a String),
+}
+
+pub(crate) enum Content<'a> {
+    /// The regular file at the path.
+    File(PathBuf),
+    Dir,
+    /// A symlink to the target.
+    Symlink(PathBuf),
+    Data(&'a [u8]),
+}
+
+/// A single archive entry, with directories already expanded into their contents.
+pub(crate) struct Entry<'a> {
+    pub name: String,
+    pub content: Content<'a>,
+    pub mode: u32,
+    pub mtime: i64,
+    pub uid: u64,
+    pub gid: u64,
+}
+
+impl<'a> Entry<'a> {
+    fn data(name: &str, data: &'a [u8]) -> Self {
+        Entry {
+            name: name.to_string(),
+            content: Content::Data(data),
+            mode: 0o644,
+            mtime: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |it| it.as_secs() as i64),
+            uid: 0,
+            gid: 0,
+        }
+    }
+
+    /// Appends `path` as `name`. With `recursive`, directories are followed
+    /// by their contents in name order.
+    fn walk(path: &Path, name: &str, recursive: bool, entries: &mut Vec<Self>) -> Result<()> {
+        let meta = fs::symlink_metadata(path).with_path(path)?;
+        let kind = FileKind::of(&meta);
+        entries.push(Entry {
+            name: name.to_string(),
+            content: match kind {
+                FileKind::File => Content::File(path.to_path_buf()),
+                FileKind::Dir => Content::Dir,
+                FileKind::Symlink => Content::Symlink(fs::read_link(path).with_path(path)?),
+            },
+            mode: meta.mode() & 0o7777,
+            mtime: meta.mtime(),
+            uid: meta.uid().into(),
+            gid: meta.gid().into(),
+        });
+        if kind != FileKind::Dir || !recursive {
+            return Ok(());
+        }
+
+        let mut children = fs::read_dir(path)
+            .and_then(|children| {
+                children
+                    .map(|it| it.map(|it| it.file_name()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .with_path(path)?;
+        children.sort();
+        for child in children {
+            let child_name = format!("{}/{}", name, child.to_string_lossy());
+            Self::walk(&path.join(&child), &child_name, true, entries)?;
+        }
+        Ok(())
+    }
+
+    /// Strips everything specific to the machine and the checkout the
+    /// package is built from.
+    fn normalize(&mut self, mtime: i64) {
+        self.mtime = mtime;
+        self.uid = 0;
+        self.gid = 0;
+        self.mode = match self.content {
+            Content::Symlink(_) => 0o777,
+            Content::Dir => 0o755,
+            _ if self.mode & 0o111 != 0 => 0o755,
+            _ => 0o644,
+        };
+    }
+}
+
+pub struct Archiver<'a> {
+    files: Vec<ArchivedFile<'a>>,
+}
+
+impl<'a> Archiver<'a> {
+    pub fn new(files: Vec<ArchivedFile<'a>>) -> Self {
+        Self { files }
+    }
+
+    pub fn compress(&self, archive: &Path, options: &BuildOptions) -> Result<()> {
+        options.validate()?;
+
+        let mut entries = Vec::new();
+        for file in self.files.iter() {
+            let (path, name, recursive) = match file {
+                ArchivedFile::FileWithNewName(path, name) => (path.as_str(), name.as_str(), true),
+                ArchivedFile::Dir(path, name) => (path.as_str(), name.as_str(), false),
+                ArchivedFile::Data(name, data) => {
+                    entries.push(Entry::data(name, data));
+                    continue;
+                }
+            };
+            Entry::walk(
+                Path::new(path),
+                name.trim_end_matches('/'),
+                recursive,
+                &mut entries,
+            )?;
+        }
+
+        if let Some(mtime) = options.reproducible {
+            // Comparing by components keeps every directory before its contents.
+            entries.sort_by(|a, b| a.name.split('/').cmp(b.name.split('/')));
+            entries.iter_mut().for_each(|it| it.normalize(mtime));
+        }
+        options
+            .format
+            .backend()
+            .compress(archive, &entries, options)
+    }
+}
+
+/// Reads packages in any [`PackageFormat`], from a file or from any other
+/// seekable source.
+pub struct Extractor<R> {
+    /// Name of the package in errors.
+    archive: PathBuf,
+    source: R,
+}
+
+impl Extractor<File> {
+    pub fn open(archive: &Path) -> Result<Self> {
+        let file = File::open(archive).with_path(archive)?;
+        Ok(Self::new(archive.to_path_buf(), file))
+    }
+}
+
+impl<R: Read + Seek> Extractor<R> {
+    pub fn new(archive: PathBuf, source: R) -> Self {
+        Self { archive, source }
+    }
+
+    pub fn format(&mut self) -> Result<PackageFormat> {
+        PackageFormat::detect(&self.archive, &mut self.source)
+    }
+
+    pub fn read_file(&mut self, name: &str) -> Result<String> {
+        self.format()?
+            .backend()
+            .read_file(&self.archive, &mut self.source, name)
+    }
+
+    pub fn extract(&mut self, destination: &Path) -> Result<()> {
+        self.format()?
+            .backend()
+            .extract(&self.archive, &mut self.source, destination)
+    }
+
+    /// Lists the entries without extracting them.
+    pub fn list(&mut self) -> Result<Vec<ArchiveEntry>> {
+        self.format()?
+            .backend()
+            .list(&self.archive, &mut self.source)
+    }
+}
+
+/// Writes extracted entries below a destination directory, refusing every
+/// entry that would end up outside of it.
+pub(crate) struct Extraction<'a> {
+    archive: &'a Path,
+    destination: &'a Path,
+    /// The canonical destination.
+    root: PathBuf,
+    dirs: Vec<(PathBuf, Option<u32>, Option<FileTime>)>,
+    links: Vec<PathBuf>,
+}
+
+impl<'a> Extraction<'a> {
+    pub fn new(archive: &'a Path, destination: &'a Path) -> Result<Self> {
+        Ok(Self {
+            archive,
+            destination,
+            root: fs::canonicalize(destination).with_path(destination)?,
+            dirs: Vec::new(),
+            links: Vec::new(),
+        })
+    }
+
+    pub fn error(&self, message: impl Display) -> Error {
+        Error::archive(self.archive, message)
+    }
+
+    /// Where the entry `name` is written to.
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        if !is_relative(Path::new(name)) {
+            return Err(self.error(format!("'{}' is outside the package", name)));
+        }
+        let path = self.destination.join(name);
+        self.check_inside(&path)?;
+        // A link extracted earlier must not be written through.
+        if fs::symlink_metadata(&path).is_ok_and(|it| it.is_symlink()) {
+            return Err(self.error(format!("'{}' is already extracted as a symlink", name)));
+        }
+        Ok(path)
+    }
+
+    /// Fails if the closest existing parent of `path` resolves to outside of
+    /// the destination, as writing `path` would then follow a symlink out of it.
+    fn check_inside(&self, path: &Path) -> Result<()> {
+        let Some(parent) = path.ancestors().skip(1).find(|it| it.exists()) else {
+            return Ok(());
+        };
+        let resolved = fs::canonicalize(parent).with_path(parent)?;
+        match resolved.starts_with(&self.root) {
+            true => Ok(()),
+            false => Err(self.error(format!(
+                "{} resolves to {} outside the package",
+                path.display(),
+                resolved.display()
+            ))),
+        }
+    }
+
+    fn create_parent(path: &Path) -> Result<()> {
+        match path.parent() {
+            Some(parent) if !parent.exists() => fs::create_dir_all(parent).with_path(parent),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn dir(&mut self, name: &str, mode: Option<u32>, mtime: Option<FileTime>) -> Result<()> {
+        // Tarballs made with `tar -C dir .` have an entry for the root itself.
+        if Path::new(name)
+            .components()
+            .all(|it| it == Component::CurDir)
+        {
+            return Ok(());
+        }
+        let path = self.path(name)?;
+        fs::create_dir_all(&path).with_path(&path)?;
+        self.dirs.push((path, mode, mtime));
+        Ok(())
+    }
+
+    pub fn symlink(&mut self, name: &str, target: &str, mtime: Option<FileTime>) -> Result<()> {
+        let path = self.path(name)?;
+        Self::create_parent(&path)?;
+
+        // The target is resolved from where the link really ends up, which
+        // differs from its name when a parent directory is a link itself.
+        let parent = path.parent().unwrap();
+        let resolved = fs::canonicalize(parent).with_path(parent)?;
+        let link = resolved
+            .strip_prefix(&self.root)
+            .unwrap_or(&resolved)
+            .join(path.file_name().unwrap());
+        if !resolved.starts_with(&self.root) || !is_contained_link(&link, Path::new(target)) {
+            return Err(self.error(format!(
+                "symlink '{}' to {} points outside the package",
+                name, target
+            )));
+        }
+        unix::fs::symlink(target, &path).with_path(&path)?;
+        set_metadata(&path, FileKind::Symlink, None, mtime).with_path(&path)?;
+        self.links.push(path);
+        Ok(())
+    }
+
+    pub fn file(
+        &mut self,
+        name: &str,
+        content: &mut impl Read,
+        mode: Option<u32>,
+        mtime: Option<FileTime>,
+    ) -> Result<()> {
+        let path = self.path(name)?;
+        Self::create_parent(&path)?;
+        // Entries stored twice replace the earlier one.
+        if fs::symlink_metadata(&path).is_ok_and(|it| it.is_file()) {
+            fs::remove_file(&path).with_path(&path)?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .with_path(&path)?;
+        // Reading fails on truncated or corrupted entries, so this is an archive error.
+        io::copy(content, &mut file).map_err(|err| self.error(format!("{}: {}", name, err)))?;
+        set_metadata(&path, FileKind::File, mode, mtime).with_path(&path)
+    }
+
+    /// Checks the extracted symlinks and restores the directory metadata.
+    pub fn finish(self) -> Result<()> {
+        // Links are checked once their targets are extracted as well.
+        for link in self.links.iter() {
+            match fs::canonicalize(link) {
+                Ok(target) if !target.starts_with(&self.root) => {
+                    return Err(self.error(format!(
+                        "{} resolves to {} outside the package",
+                        link.display(),
+                        target.display()
+                    )))
+                }
+                _ => {}
+            }
+        }
+
+        for (dir, mode, mtime) in self.dirs.iter().rev() {
+            set_metadata(dir, FileKind::Dir, *mode, *mtime).with_path(dir)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Result, WithPath},
+    event::Event,
+    package::Package,
+    program::Program,
+    repository::IndexEntry,
+    signature,
+    util::sha256_file,
+    Ebpm,
+};
+
+const CACHE_DIR: &str = "cache";
+
+/// A package kept in the cache, which is named after its SHA-256.
+pub struct CachedPackage {
+    pub entry: IndexEntry,
+    /// Whether an installed version, or the one kept for a rollback, was
+    /// installed from it.
+    pub in_use: bool,
+}
+
+pub fn dir(ebpm: &Ebpm) -> PathBuf {
+    ebpm.root().join(CACHE_DIR)
+}
+
+/// Path of the cached package with the given hash.
+pub fn path(ebpm: &Ebpm, sha256: &str) -> PathBuf {
+    dir(ebpm).join(sha256)
+}
+
+/// Copies the package file `source` into the cache, along with its signature,
+/// and returns its hash. Packages that are cached already are not copied again.
+pub fn add(ebpm: &Ebpm, source: &Path) -> Result<String> {
+    let dir = dir(ebpm);
+    fs::create_dir_all(&dir).with_path(&dir)?;
+
+    let sha256 = sha256_file(source).with_path(source)?;
+    let path = dir.join(&sha256);
+    if !path.is_file() {
+        // Copied under a temporary name, so that a cached package is always complete.
+        let partial = dir.join(format!(".{}.partial", sha256));
+        fs::copy(source, &partial)
+            .and_then(|_| fs::rename(&partial, &path))
+            .with_path(&path)?;
+    }
+
+    let signature = signature::signature_path(source);
+    if signature.is_file() {
+        let cached = signature::signature_path(&path);
+        fs::copy(&signature, &cached).with_path(&cached)?;
+    }
+    Ok(sha256)
+}
+
+/// Hashes of the packages the installed programs came from.
+fn used(ebpm: &Ebpm) -> Result<BTreeSet<String>> {
+    let mut used = BTreeSet::new();
+    for program in Program::installed_programs(ebpm)? {
+        let previous = program.resources(ebpm).previous_manifest;
+        if previous.is_file() {
+            used.extend(Program::load_from(&previous)?.package);
+        }
+        used.extend(program.package);
+    }
+    Ok(used)
+}
+
+/// Every cached package, sorted by name and version.
+pub fn list(ebpm: &Ebpm) -> Result<Vec<CachedPackage>> {
+    let dir = dir(ebpm);
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .map(|entries| {
+            entries
+                .filter_map(|it| it.ok())
+                .filter_map(|it| it.file_name().into_string().ok())
+                .filter(|it| it.len() == 64 && it.bytes().all(|it| it.is_ascii_hexdigit()))
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+
+    let used = used(ebpm)?;
+    let mut packages = Vec::new();
+    for sha256 in names {
+        let path = dir.join(&sha256);
+        let manifest = match Package::new(path.clone()).manifest() {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                ebpm.report(Event::Warning(err.to_string()));
+                continue;
+            }
+        };
+
+        packages.push(CachedPackage {
+            in_use: used.contains(&sha256),
+            entry: IndexEntry {
+                name: manifest.name,
+                version: manifest.version,
+                description: manifest.description,
+                dependencies: manifest.dependencies,
+                path: sha256.clone(),
+                size: fs::metadata(&path).with_path(&path)?.len(),
+                sha256,
+            },
+        });
+    }
+    packages
+        .sort_by(|a, b| (&a.entry.name, &a.entry.version).cmp(&(&b.entry.name, &b.entry.version)));
+    Ok(packages)
+}
+
+fn remove(ebpm: &Ebpm, package: &CachedPackage) -> Result<()> {
+    let path = path(ebpm, &package.entry.sha256);
+    fs::remove_file(&path).with_path(&path)?;
+
+    let signature = signature::signature_path(&path);
+    if signature.is_file() {
+        fs::remove_file(&signature).with_path(&signature)?;
+    }
+    Ok(())
+}
+
+/// Removes every cached package that no installed program came from,
+/// returning the removed ones.
+pub fn clean(ebpm: &Ebpm) -> Result<Vec<CachedPackage>> {
+    prune(ebpm, 0)
+}
+
+/// Removes the cached packages of each program that are neither in use nor
+/// among its `keep` highest versions, returning the removed ones.
+pub fn prune(ebpm: &Ebpm, keep: usize) -> Result<Vec<CachedPackage>> {
+    let mut by_name: BTreeMap<String, Vec<CachedPackage>> = BTreeMap::new();
+    for package in list(ebpm)? {
+        by_name
+            .entry(package.entry.name.clone())
+            .or_default()
+            .push(package);
+    }
+
+    let mut removed = Vec::new();
+    for (_, mut packages) in by_name {
+        // The list is sorted by version, this puts the highest ones first.
+        packages.reverse();
+        for package in packages.into_iter().skip(keep) {
+            if !package.in_use {
+                remove(ebpm, &package)?;
+                removed.push(package);
+            }
+        }
+    }
+    Ok(removed)
+}
@@ -0,0 +1,133 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result, WithPath},
+    filetransfer::TransferStrategy,
+};
+
+/// Overrides the install root. The config file is then read from the root as well,
+/// so that a root set up this way is fully self-contained.
+pub const HOME_VAR: &str = "EBPM_HOME";
+const CONFIG_FILE: &str = "config.json";
+/// Name of the config file inside `EBPM_HOME`, hidden so that it can't be
+/// taken for the record of a program.
+const HOME_CONFIG_FILE: &str = ".config.json";
+
+/// Keys accepted by `ebpm config get/set`.
+pub const KEYS: [&str; 5] = ["root", "exe_dir", "temp_dir", "transfer", "prompt"];
+
+/// How yes/no questions of the CLI are answered.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Prompt {
+    /// Ask interactively.
+    Ask,
+    /// Answer every question with yes.
+    Yes,
+    /// Answer every question with no.
+    No,
+}
+
+impl Display for Prompt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Prompt::Ask => "ask",
+            Prompt::Yes => "yes",
+            Prompt::No => "no",
+        })
+    }
+}
+
+impl TryFrom<&str> for Prompt {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "ask" => Ok(Prompt::Ask),
+            "yes" => Ok(Prompt::Yes),
+            "no" => Ok(Prompt::No),
+            other => Err(Error::Usage(format!(
+                "'{}' is not a valid prompt, expected 'ask', 'yes' or 'no'",
+                other
+            ))),
+        }
+    }
+}
+
+/// Contents of the config file. Unset keys fall back to the defaults of [`crate::Ebpm`].
+#[derive(Serialize, Deserialize, Default)]
+pub struct Config {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temp_dir: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transfer: Option<TransferStrategy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<Prompt>,
+}
+
+impl Config {
+    /// `$EBPM_HOME/.config.json` when `EBPM_HOME` is set, otherwise
+    /// `$XDG_CONFIG_HOME/ebpm/config.json` or `~/.config/ebpm/config.json`.
+    pub fn path() -> Result<PathBuf> {
+        if let Some(home) = env::var_os(HOME_VAR) {
+            return Ok(PathBuf::from(home).join(HOME_CONFIG_FILE));
+        }
+
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|home| home.join(".config")))
+            .map(|dir| dir.join("ebpm").join(CONFIG_FILE))
+            .ok_or_else(|| Error::NotFound("Home directory is not known".into()))
+    }
+
+    /// Loads the config file, a missing file being an empty config.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let json = fs::read_to_string(path).with_path(path)?;
+        serde_json::from_str(&json).map_err(|source| Error::Manifest {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_path(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).with_path(path)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "root" => self.root = Some(value.into()),
+            "exe_dir" => self.exe_dir = Some(value.into()),
+            "temp_dir" => self.temp_dir = Some(value.into()),
+            "transfer" => self.transfer = Some(TransferStrategy::try_from(value)?),
+            "prompt" => self.prompt = Some(Prompt::try_from(value)?),
+            other => return Err(unknown_key(other)),
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn unknown_key(key: &str) -> Error {
+    Error::Usage(format!(
+        "Unknown config key '{}', expected one of: {}",
+        key,
+        KEYS.join(", ")
+    ))
+}
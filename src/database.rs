@@ -0,0 +1,253 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs, io,
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::{Result, WithPath},
+    util::sha256_file,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+impl FileKind {
+    /// Kind of an entry, given metadata that was read without following symlinks.
+    pub fn of(meta: &fs::Metadata) -> Self {
+        if meta.file_type().is_symlink() {
+            FileKind::Symlink
+        } else if meta.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Dir => "directory",
+            FileKind::Symlink => "symlink",
+        }
+    }
+}
+
+/// A path written into a program's resource directory, either by the package
+/// itself or by its install script.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledFile {
+    /// Path relative to the resource directory.
+    pub path: String,
+    pub kind: FileKind,
+    pub size: u64,
+    pub mode: u32,
+    /// Hash of the contents of files, or of the target of symlinks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Config files may be edited, so only their kind and mode are verified.
+    /// The hash is the one of the packaged version.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub config: bool,
+}
+
+/// SHA-256 of a file, or of the target of a symlink, as symlinks are
+/// checked by their target. Directories have none.
+pub(crate) fn sha256(path: &Path, kind: FileKind) -> io::Result<Option<String>> {
+    match kind {
+        FileKind::File => sha256_file(path).map(Some),
+        FileKind::Symlink => {
+            let target = fs::read_link(path)?;
+            Ok(Some(format!(
+                "{:x}",
+                Sha256::digest(target.as_os_str().as_bytes())
+            )))
+        }
+        FileKind::Dir => Ok(None),
+    }
+}
+
+/// Records every entry below `root`, parents before their children.
+pub fn scan(root: &Path) -> Result<Vec<InstalledFile>> {
+    fn visit(root: &Path, dir: &Path, files: &mut Vec<InstalledFile>) -> Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|it| it.path()))
+                    .collect::<io::Result<Vec<PathBuf>>>()
+            })
+            .with_path(dir)?;
+        entries.sort();
+
+        for path in entries {
+            let meta = fs::symlink_metadata(&path).with_path(&path)?;
+            let kind = FileKind::of(&meta);
+
+            files.push(InstalledFile {
+                path: path
+                    .strip_prefix(root)
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned(),
+                kind,
+                size: meta.len(),
+                mode: meta.permissions().mode() & 0o7777,
+                sha256: sha256(&path, kind).with_path(&path)?,
+                config: false,
+            });
+
+            if kind == FileKind::Dir {
+                visit(root, &path, files)?;
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = Vec::new();
+    visit(root, root, &mut files)?;
+    Ok(files)
+}
+
+/// Removes the recorded entries below `root`, leaving anything that was not
+/// recorded in place. Returns whether `root` itself could be removed.
+pub fn remove(root: &Path, files: &[InstalledFile]) -> Result<bool> {
+    remove_entries(files.iter().map(|file| (root.join(&file.path), file.kind)))?;
+    Ok(fs::remove_dir(root).is_ok())
+}
+
+/// Removes the `entries`, which list directories ahead of their contents.
+pub(crate) fn remove_entries(
+    entries: impl DoubleEndedIterator<Item = (PathBuf, FileKind)>,
+) -> Result<()> {
+    for (path, kind) in entries.rev() {
+        let result = match kind {
+            FileKind::Dir => match fs::remove_dir(&path) {
+                // Directories that still hold other files are kept.
+                Err(_) if path.is_dir() => Ok(()),
+                other => other,
+            },
+            _ => fs::remove_file(&path),
+        };
+
+        match result {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err).with_path(&path),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A way in which a resource directory deviates from what was recorded.
+pub enum Difference {
+    Missing(String),
+    Unexpected(String),
+    Modified { path: String, reason: String },
+}
+
+impl Difference {
+    pub fn path(&self) -> &str {
+        match self {
+            Difference::Missing(path) | Difference::Unexpected(path) => path,
+            Difference::Modified { path, .. } => path,
+        }
+    }
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Missing(path) => write!(f, "missing {}", path),
+            Difference::Unexpected(path) => write!(f, "unexpected {}", path),
+            Difference::Modified { path, reason } => write!(f, "modified {} ({})", path, reason),
+        }
+    }
+}
+
+/// Compares the `actual` entries of a resource directory, as returned by
+/// [`scan`], against the `recorded` ones. Entries below a missing or
+/// unexpected directory are not reported on their own.
+pub fn diff(recorded: &[InstalledFile], actual: &[InstalledFile]) -> Vec<Difference> {
+    let recorded: BTreeMap<&str, &InstalledFile> =
+        recorded.iter().map(|it| (it.path.as_str(), it)).collect();
+    let actual: BTreeMap<&str, &InstalledFile> =
+        actual.iter().map(|it| (it.path.as_str(), it)).collect();
+
+    // Paths whose children are covered by the difference of the path itself.
+    let mut covered = Vec::new();
+    let mut differences = Vec::new();
+    for (path, file) in recorded.iter() {
+        match actual.get(path) {
+            None => {
+                covered.push(*path);
+                differences.push(Difference::Missing(path.to_string()));
+            }
+            Some(current) => {
+                if current.kind != file.kind {
+                    covered.push(*path);
+                }
+                if let Some(reason) = change(file, current) {
+                    differences.push(Difference::Modified {
+                        path: path.to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+    for path in actual.keys().filter(|path| !recorded.contains_key(*path)) {
+        covered.push(*path);
+        differences.push(Difference::Unexpected(path.to_string()));
+    }
+
+    differences.retain(|difference| {
+        let path = difference.path();
+        !covered
+            .iter()
+            .any(|dir| path.strip_prefix(dir).is_some_and(|it| it.starts_with('/')))
+    });
+    differences.sort_by(|a, b| a.path().cmp(b.path()));
+    differences
+}
+
+/// Describes how `current` differs from `recorded`, if it does.
+fn change(recorded: &InstalledFile, current: &InstalledFile) -> Option<String> {
+    if recorded.kind != current.kind {
+        return Some(format!(
+            "{} replaced by a {}",
+            recorded.kind.name(),
+            current.kind.name()
+        ));
+    }
+
+    let content_changed = match (&recorded.sha256, &current.sha256) {
+        _ if recorded.config => false,
+        (Some(expected), Some(actual)) => expected != actual,
+        // Records written before symlinks were hashed only have the size.
+        _ => recorded.kind != FileKind::Dir && recorded.size != current.size,
+    };
+    if content_changed {
+        return Some(match recorded.kind {
+            FileKind::Symlink => "target changed".into(),
+            _ => "content changed".into(),
+        });
+    }
+
+    // The mode of a symlink is not its own on Linux.
+    if recorded.kind != FileKind::Symlink && recorded.mode != current.mode {
+        return Some(format!(
+            "mode {:04o}, expected {:04o}",
+            current.mode, recorded.mode
+        ));
+    }
+    None
+}
@@ -0,0 +1,132 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    error::Result,
+    program::{Manifest, Program},
+    Ebpm,
+};
+
+pub(crate) struct Resolution {
+    /// Indices into the resolved manifests, dependencies first.
+    pub(crate) order: Vec<usize>,
+    pub(crate) problems: Vec<String>,
+}
+
+pub(crate) fn resolve(ebpm: &Ebpm, manifests: &[&Manifest]) -> Result<Resolution> {
+    let mut problems = Vec::new();
+    let mut by_name: HashMap<&str, usize> = HashMap::new();
+
+    for (index, manifest) in manifests.iter().enumerate() {
+        if let Some(&other) = by_name.get(manifest.name.as_str()) {
+            problems.push(format!(
+                "Conflict: {} is given twice ({} and {})",
+                manifest.name, manifests[other].version, manifest.version
+            ));
+            continue;
+        }
+        by_name.insert(&manifest.name, index);
+    }
+
+    for manifest in manifests.iter() {
+        for (name, requirement) in manifest.dependencies.iter() {
+            let version = match by_name.get(name.as_str()) {
+                Some(&index) => manifests[index].version.clone(),
+                None if Program::is_installed(ebpm, name) => Program::load(ebpm, name)?.version,
+                None => {
+                    problems.push(format!(
+                        "{} requires {} {}, which is neither installed nor being installed",
+                        manifest.name, name, requirement
+                    ));
+                    continue;
+                }
+            };
+
+            if !requirement.matches(&version) {
+                problems.push(format!(
+                    "{} requires {} {}, but version {} would be installed",
+                    manifest.name, name, requirement, version
+                ));
+            }
+        }
+    }
+
+    // Installed programs that stay as they are must still be satisfied by the
+    // versions replacing their dependencies.
+    for program in Program::installed_programs(ebpm)? {
+        if by_name.contains_key(program.name.as_str()) || !program.is_active(ebpm) {
+            continue;
+        }
+        for (name, requirement) in program.dependencies.iter() {
+            let Some(&index) = by_name.get(name.as_str()) else {
+                continue;
+            };
+            let version = &manifests[index].version;
+            if !requirement.matches(version) {
+                problems.push(format!(
+                    "{} {} requires {} {}, but version {} would be installed",
+                    program.name, program.version, name, requirement, version
+                ));
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(manifests.len());
+    let mut visited = vec![false; manifests.len()];
+    let mut in_progress = vec![false; manifests.len()];
+
+    fn visit(
+        index: usize,
+        manifests: &[&Manifest],
+        by_name: &HashMap<&str, usize>,
+        visited: &mut [bool],
+        in_progress: &mut [bool],
+        order: &mut Vec<usize>,
+        problems: &mut Vec<String>,
+    ) {
+        if visited[index] {
+            return;
+        }
+        if in_progress[index] {
+            problems.push(format!(
+                "Dependency cycle detected at {}",
+                manifests[index].name
+            ));
+            return;
+        }
+
+        in_progress[index] = true;
+        for name in manifests[index].dependencies.keys() {
+            if let Some(&dependency) = by_name.get(name.as_str()) {
+                visit(
+                    dependency,
+                    manifests,
+                    by_name,
+                    visited,
+                    in_progress,
+                    order,
+                    problems,
+                );
+            }
+        }
+        in_progress[index] = false;
+
+        if !visited[index] {
+            visited[index] = true;
+            order.push(index);
+        }
+    }
+
+    for index in by_name.values().copied().collect::<BTreeSet<_>>() {
+        visit(
+            index,
+            manifests,
+            &by_name,
+            &mut visited,
+            &mut in_progress,
+            &mut order,
+            &mut problems,
+        );
+    }
+
+    Ok(Resolution { order, problems })
+}
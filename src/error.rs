@@ -0,0 +1,136 @@
+use std::{
+    fmt::{self, Display},
+    io,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    result,
+};
+
+/// Everything that can make an ebpm operation fail.
+///
+/// Each variant maps to a distinct process exit code, see [`Error::exit_code`].
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O operation on `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// A manifest or another ebpm JSON file could not be parsed.
+    Manifest {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// A package archive is malformed or cannot be read.
+    Archive { path: PathBuf, message: String },
+    /// A package failed its checksum or signature verification, or one of its
+    /// paths or symlinks leads outside the package.
+    Integrity { path: PathBuf, message: String },
+    /// An install, remove or program script exited unsuccessfully.
+    Script { script: String, status: ExitStatus },
+    /// The operation conflicts with versions, dependencies or files already present.
+    Conflict(String),
+    /// A program, package, key or repository does not exist.
+    NotFound(String),
+    /// The command line is invalid.
+    Usage(String),
+    /// The operation was interrupted with Ctrl-C.
+    Interrupted,
+}
+
+pub type Result<T, E = Error> = result::Result<T, E>;
+
+impl Error {
+    /// Exit codes of the `ebpm` binary:
+    ///
+    /// | code | meaning                    |
+    /// |------|----------------------------|
+    /// | 0    | success                    |
+    /// | 2    | invalid command line       |
+    /// | 3    | not found                  |
+    /// | 4    | conflict                   |
+    /// | 5    | manifest parse error       |
+    /// | 6    | malformed archive          |
+    /// | 7    | integrity check failure    |
+    /// | 8    | script failure             |
+    /// | 9    | I/O error                  |
+    /// | 130  | interrupted                |
+    ///
+    /// `ebpm run` exits with the exit code of the program it runs instead, so
+    /// there a code may come from the program as well as from ebpm. Only a
+    /// program killed by a signal is reported as [`Error::Script`].
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => 2,
+            Error::NotFound(_) => 3,
+            Error::Conflict(_) => 4,
+            Error::Manifest { .. } => 5,
+            Error::Archive { .. } => 6,
+            Error::Integrity { .. } => 7,
+            Error::Script { .. } => 8,
+            Error::Io { .. } => 9,
+            Error::Interrupted => 130,
+        }
+    }
+
+    pub(crate) fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    pub(crate) fn archive(path: impl AsRef<Path>, message: impl Display) -> Self {
+        Error::Archive {
+            path: path.as_ref().to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn integrity(path: impl AsRef<Path>, message: impl Display) -> Self {
+        Error::Integrity {
+            path: path.as_ref().to_path_buf(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Manifest { path, source } => {
+                write!(f, "Failed to parse {}: {}", path.display(), source)
+            }
+            Error::Archive { path, message } => {
+                write!(f, "Invalid package {}: {}", path.display(), message)
+            }
+            Error::Integrity { path, message } => {
+                write!(f, "Verification of {} failed: {}", path.display(), message)
+            }
+            Error::Script { script, status } => write!(f, "'{}' failed with {}", script, status),
+            Error::Conflict(message) | Error::NotFound(message) | Error::Usage(message) => {
+                write!(f, "{}", message)
+            }
+            Error::Interrupted => write!(f, "Interrupted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Manifest { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Attaches the offending path to I/O errors.
+pub trait WithPath<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T> WithPath<T> for io::Result<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|err| Error::io(path, err))
+    }
+}
@@ -0,0 +1,57 @@
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+use semver::Version;
+
+/// Progress and warnings of a running operation, passed to the reporter set
+/// with [`Ebpm::set_reporter`](crate::Ebpm::set_reporter). Displaying an
+/// event gives the line `ebpm` prints for it.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A package is about to be installed.
+    Installing { name: String, version: Version },
+    /// A package has been installed.
+    Installed { name: String, version: Version },
+    /// The upgrade is skipped as the version is installed already.
+    AlreadyInstalled { name: String, version: Version },
+    /// An installed program is about to be replaced by another version.
+    Upgrading {
+        name: String,
+        from: Version,
+        to: Version,
+    },
+    /// A version of a program is about to be removed.
+    Removing { name: String, version: Version },
+    /// A version of a program has been removed.
+    Removed { name: String, version: Version },
+    /// The signature of a package was verified with the trusted key `key`.
+    SignatureVerified { archive: PathBuf, key: String },
+    /// Something went wrong without failing the operation.
+    Warning(String),
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Installing { name, version } => write!(f, "installing {} {}", name, version),
+            Event::Installed { .. } => f.write_str("Installing finished"),
+            Event::AlreadyInstalled { name, version } => {
+                write!(f, "{} {} is already installed", name, version)
+            }
+            Event::Upgrading { name, from, to } => {
+                write!(f, "Upgrading {} {} -> {}", name, from, to)
+            }
+            Event::Removing { name, version } => write!(f, "Removing {} {}...", name, version),
+            Event::Removed { .. } => f.write_str("Removing finished"),
+            Event::SignatureVerified { archive, key } => write!(
+                f,
+                "Signature of {} verified with key '{}'",
+                archive.display(),
+                key
+            ),
+            Event::Warning(message) => write!(f, "Warning: {}", message),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Component, Path},
+};
+
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::FileKind,
+    error::{Error, Result, WithPath},
+    program::Manifest,
+};
+
+/// Exclude patterns read from the package directory, one per line.
+pub const IGNORE_FILE: &str = ".ebpmignore";
+const MANIFEST_FILE: &str = "manifest.ebpm.json";
+
+const OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: true,
+};
+
+/// An entry of the manifest `files`: either a path or glob pattern, or a
+/// `{"src": ..., "dst": ...}` mapping of a path to a destination inside the
+/// resource directory.
+///
+/// Absolute paths and paths with `..` are rejected when the manifest is loaded.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged, try_from = "RawEntry")]
+pub enum FileEntry {
+    Path(String),
+    Mapped { src: String, dst: String },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawEntry {
+    Path(String),
+    Mapped { src: String, dst: String },
+}
+
+impl TryFrom<RawEntry> for FileEntry {
+    type Error = String;
+
+    fn try_from(entry: RawEntry) -> Result<Self, String> {
+        let check = |path: &str, inside: &str| match is_relative(Path::new(path)) {
+            true => Ok(()),
+            false => Err(format!("'{}' is not a path inside the {}", path, inside)),
+        };
+        match entry {
+            RawEntry::Path(path) => {
+                check(&path, "package")?;
+                Ok(FileEntry::Path(path))
+            }
+            RawEntry::Mapped { src, dst } => {
+                check(&src, "package")?;
+                check(&dst, "resource directory")?;
+                Ok(FileEntry::Mapped { src, dst })
+            }
+        }
+    }
+}
+
+impl FileEntry {
+    /// The entry as recorded in a built package. Mapped files are archived at
+    /// their destination, so they are mapped onto themselves there.
+    pub fn archived(&self) -> Self {
+        match self {
+            FileEntry::Path(path) => FileEntry::Path(path.clone()),
+            FileEntry::Mapped { dst, .. } => FileEntry::Mapped {
+                src: normalize(dst).to_string(),
+                dst: dst.clone(),
+            },
+        }
+    }
+}
+
+/// A file or directory of a package after expanding the manifest `files`.
+pub struct PackageFile {
+    /// Path relative to the package root.
+    pub src: String,
+    /// Path relative to the program resource directory.
+    pub dst: String,
+    /// Name in the archive, which is `dst` for mapped entries and `src` otherwise.
+    pub archived: String,
+    pub kind: FileKind,
+}
+
+/// A pattern without a `/` matches file names at any depth, otherwise the
+/// whole path relative to the package root, like in `.gitignore`.
+struct Exclude {
+    pattern: Pattern,
+    anchored: bool,
+}
+
+impl Exclude {
+    fn parse(line: &str) -> Result<Self> {
+        let trimmed = line.trim_end_matches('/');
+        let anchored = trimmed.contains('/');
+        Ok(Self {
+            pattern: parse_pattern(trimmed.trim_start_matches('/'))?,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let name = match self.anchored {
+            true => path,
+            false => path.rsplit('/').next().unwrap_or(path),
+        };
+        self.pattern.matches_with(name, OPTIONS)
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Pattern> {
+    Pattern::new(pattern)
+        .map_err(|err| Error::Usage(format!("Invalid pattern '{}': {}", pattern, err)))
+}
+
+fn is_glob(entry: &str) -> bool {
+    entry.contains(['*', '?', '['])
+}
+
+/// Whether `path` is relative and has no `..` components.
+pub(crate) fn is_relative(path: &Path) -> bool {
+    path.components()
+        .all(|it| matches!(it, Component::Normal(_) | Component::CurDir))
+}
+
+/// Whether the symlink at `link` with `target` resolves to a path inside the
+/// directory both are relative to. Only the paths are looked at, so links
+/// through other symlinks are not followed.
+pub fn is_contained_link(link: &Path, target: &Path) -> bool {
+    let mut depth = 0usize;
+    let parent = link.parent().unwrap_or(Path::new(""));
+    for component in parent.components().chain(target.components()) {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn normalize(path: &str) -> &str {
+    path.trim_start_matches("./").trim_end_matches('/')
+}
+
+/// Reads the `.ebpmignore` of the package at `base`. Empty lines and lines
+/// starting with `#` are skipped.
+pub fn ignore_patterns(base: &Path) -> Result<Vec<String>> {
+    let path = base.join(IGNORE_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_path(path),
+    }
+}
+
+struct Expansion<'a> {
+    base: &'a Path,
+    excludes: Vec<Exclude>,
+    seen: BTreeSet<String>,
+    files: Vec<PackageFile>,
+}
+
+impl Expansion<'_> {
+    fn is_excluded(&self, path: &str) -> bool {
+        self.excludes.iter().any(|it| it.matches(path))
+    }
+
+    fn children(&self, dir: &str) -> Result<Vec<(String, String)>> {
+        let path = self.base.join(dir);
+        let mut names = fs::read_dir(&path)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| entry.map(|it| it.file_name().to_string_lossy().into_owned()))
+                    .collect::<io::Result<Vec<String>>>()
+            })
+            .with_path(&path)?;
+        names.sort();
+        Ok(names
+            .into_iter()
+            .map(|name| match dir.is_empty() {
+                true => (name.clone(), name),
+                false => (format!("{}/{}", dir, name), name),
+            })
+            .collect())
+    }
+
+    /// Adds `src` at `dst`, descending into directories but not into symlinks.
+    fn add(&mut self, src: String, dst: String, mapped: bool) -> Result<()> {
+        if !self.seen.insert(src.clone()) {
+            return Ok(());
+        }
+
+        let path = self.base.join(&src);
+        let kind = FileKind::of(&fs::symlink_metadata(&path).with_path(&path)?);
+        if kind == FileKind::Symlink {
+            let target = fs::read_link(&path).with_path(&path)?;
+            if !is_contained_link(Path::new(&dst), &target) {
+                return Err(Error::integrity(
+                    &path,
+                    format!("symlink to {} points outside the package", target.display()),
+                ));
+            }
+        }
+        self.files.push(PackageFile {
+            archived: match mapped {
+                true => dst.clone(),
+                false => src.clone(),
+            },
+            src: src.clone(),
+            dst: dst.clone(),
+            kind,
+        });
+
+        if kind == FileKind::Dir {
+            for (child, name) in self.children(&src)? {
+                if !self.is_excluded(&child) {
+                    self.add(child, format!("{}/{}", dst, name), mapped)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every path below the package root that is not excluded, parents first.
+    fn walk(&self, dir: &str, skip: &str, paths: &mut Vec<String>) -> Result<()> {
+        for (child, name) in self.children(dir)? {
+            if self.is_excluded(&child) || (dir.is_empty() && name.starts_with(skip)) {
+                continue;
+            }
+            paths.push(child.clone());
+            let meta = fs::symlink_metadata(self.base.join(&child));
+            if meta.is_ok_and(|it| it.is_dir()) {
+                self.walk(&child, skip, paths)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Expands the `files` of the package at `base`.
+///
+/// Literal entries are taken as they are and land in the resource directory
+/// under their file name. Glob patterns such as `assets/**/*.png` keep their
+/// path relative to the package root, and mapped entries land at their `dst`.
+/// Directories are included recursively, leaving out everything matched by
+/// the manifest `exclude` list or by the `.ebpmignore` file. Explicitly listed
+/// paths are never excluded.
+pub fn expand(base: &Path, manifest: &Manifest) -> Result<Vec<PackageFile>> {
+    let excludes = manifest
+        .exclude
+        .iter()
+        .cloned()
+        .chain(ignore_patterns(base)?)
+        .map(|it| Exclude::parse(&it))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut expansion = Expansion {
+        base,
+        excludes,
+        seen: BTreeSet::new(),
+        files: Vec::new(),
+    };
+    let mut tree = None;
+
+    for entry in manifest.files.iter() {
+        let entry = match entry {
+            FileEntry::Mapped { src, dst } => {
+                let (src, dst) = (normalize(src), normalize(dst));
+                if is_glob(src) || dst.is_empty() {
+                    return Err(Error::Usage(format!(
+                        "'{}' -> '{}' is not a valid file mapping",
+                        src, dst
+                    )));
+                }
+                expansion.add(src.to_string(), dst.to_string(), true)?;
+                continue;
+            }
+            FileEntry::Path(entry) => normalize(entry),
+        };
+
+        if !is_glob(entry) {
+            let name = Path::new(entry)
+                .file_name()
+                .ok_or_else(|| Error::Usage(format!("'{}' is not a valid file entry", entry)))?;
+            expansion.add(
+                entry.to_string(),
+                name.to_string_lossy().into_owned(),
+                false,
+            )?;
+            continue;
+        }
+
+        let pattern = parse_pattern(entry)?;
+        if tree.is_none() {
+            let mut paths = Vec::new();
+            // Neither the manifest nor previously built archives are package contents.
+            expansion.walk("", &format!("{}.ebpm.", manifest.name), &mut paths)?;
+            paths.retain(|path| path != MANIFEST_FILE);
+            tree = Some(paths);
+        }
+
+        let matches: Vec<String> = tree
+            .iter()
+            .flatten()
+            .filter(|path| pattern.matches_with(path, OPTIONS))
+            .cloned()
+            .collect();
+        if matches.is_empty() {
+            return Err(Error::NotFound(format!(
+                "'{}' matches no files in {}",
+                entry,
+                base.display()
+            )));
+        }
+        for path in matches {
+            expansion.add(path.clone(), path, false)?;
+        }
+    }
+
+    let mut archived = BTreeSet::new();
+    if let Some(file) = expansion
+        .files
+        .iter()
+        .find(|file| !archived.insert(file.archived.as_str()))
+    {
+        return Err(Error::Conflict(format!(
+            "'{}' is listed twice in the package files",
+            file.archived
+        )));
+    }
+    let mut installed = BTreeSet::new();
+    if let Some(file) = expansion
+        .files
+        .iter()
+        .find(|file| !installed.insert(file.dst.as_str()))
+    {
+        return Err(Error::Conflict(format!(
+            "'{}' is the destination of more than one of the package files",
+            file.dst
+        )));
+    }
+    Ok(expansion.files)
+}
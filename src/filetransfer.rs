@@ -1,85 +1,139 @@
-use std::{fs, io, path::Path};
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    os::unix::{
+        self,
+        fs::{MetadataExt, PermissionsExt},
+    },
+    path::Path,
+};
 
-#[derive(Copy, Clone)]
-pub(crate) enum TransferStrategy {
+use filetime::FileTime;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    database::{self, FileKind},
+    error::{Error, Result, WithPath},
+    files::PackageFile,
+};
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferStrategy {
     Move,
     Copy,
 }
 
-pub struct FilesTransfer {
-    strategy: TransferStrategy,
+impl Display for TransferStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TransferStrategy::Move => "move",
+            TransferStrategy::Copy => "copy",
+        })
+    }
 }
 
-impl FilesTransfer {
-    pub fn transfer_file(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-        match self.strategy {
-            TransferStrategy::Move => {
-                match fs::rename(src.as_ref(), dst.as_ref()) {
-                    Ok(_) => Ok(()),
-                    Err(_) => match fs::copy(src.as_ref(), dst.as_ref()) {
-                        Ok(_) => fs::remove_file(src).map_err(|err| {
-                            eprintln!("Failed to remove source file: {}", err);
-                            err
-                        }),
-                        Err(err) => {
-                            eprintln!("Failed to copy file: {}", err);
-                            Err(err)
-                        }
-                    },
-                }?;
-            }
-            TransferStrategy::Copy => {
-                fs::copy(src, dst)?;
-            }
-        };
+impl TryFrom<&str> for TransferStrategy {
+    type Error = Error;
 
-        Ok(())
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "move" => Ok(TransferStrategy::Move),
+            "copy" => Ok(TransferStrategy::Copy),
+            other => Err(Error::Usage(format!(
+                "'{}' is not a valid transfer strategy, expected 'move' or 'copy'",
+                other
+            ))),
+        }
     }
+}
 
-    fn recoursive_copy(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-        fs::create_dir_all(&dst)?;
-
-        for entry in fs::read_dir(src)? {
-            let entry = entry?;
+pub struct FilesTransfer {
+    strategy: TransferStrategy,
+}
 
-            let ty = entry.file_type()?;
-            if ty.is_dir() {
-                self.recoursive_copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
-            } else {
-                self.transfer_file(entry.path(), dst.as_ref().join(entry.file_name()))?;
-            }
-        }
-        Ok(())
+/// Sets the permission bits and modification time of `path`, without
+/// following symlinks. Symlinks have no permissions of their own.
+pub(crate) fn set_metadata(
+    path: &Path,
+    kind: FileKind,
+    mode: Option<u32>,
+    mtime: Option<FileTime>,
+) -> io::Result<()> {
+    if let (Some(mode), false) = (mode, kind == FileKind::Symlink) {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
     }
+    if let Some(mtime) = mtime {
+        filetime::set_symlink_file_times(path, mtime, mtime)?;
+    }
+    Ok(())
+}
 
-    fn copy(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-        if let Ok(file) = fs::metadata(&src) {
-            if file.is_file() {
-                let file = src.as_ref().file_name().unwrap();
-                self.transfer_file(src.as_ref(), dst.as_ref().join(file))?;
+impl FilesTransfer {
+    /// Transfers a file or symlink, keeping its mode and modification time.
+    pub fn transfer_file(&self, src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+        let (src, dst) = (src.as_ref(), dst.as_ref());
+        if let TransferStrategy::Move = self.strategy {
+            if fs::rename(src, dst).is_ok() {
                 return Ok(());
             }
         }
 
-        let dir = src
-            .as_ref()
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+        let meta = fs::symlink_metadata(src)?;
+        let kind = FileKind::of(&meta);
+        match kind {
+            FileKind::Symlink => unix::fs::symlink(fs::read_link(src)?, dst)?,
+            // The permission bits are copied along with the content.
+            _ => _ = fs::copy(src, dst)?,
+        }
+        let mtime = FileTime::from_last_modification_time(&meta);
+        set_metadata(dst, kind, None, Some(mtime))?;
 
-        self.recoursive_copy(src, dst.as_ref().join(dir))
+        if let TransferStrategy::Move = self.strategy {
+            fs::remove_file(src)?;
+        }
+        Ok(())
     }
 
-    pub fn execute_transfer(&self, files: &Vec<String>, dst: impl AsRef<Path>) -> io::Result<()> {
+    /// Transfers the expanded package `files` from `base` into `dst`.
+    pub fn execute_transfer(
+        &self,
+        base: &Path,
+        files: &[PackageFile],
+        dst: impl AsRef<Path>,
+    ) -> Result<()> {
+        let mut dirs = Vec::new();
         for file in files.iter() {
-            self.copy(file, dst.as_ref())
-                .inspect_err(|err| println!("File: {}; Error: {}", file, err))?
+            let src = base.join(&file.src);
+            let target = dst.as_ref().join(&file.dst);
+            if file.kind == FileKind::Dir {
+                // Moving the contents out changes the source, so it is read up front.
+                let meta = fs::metadata(&src).with_path(&src)?;
+                fs::create_dir_all(&target).with_path(&target)?;
+                dirs.push((meta, target));
+                continue;
+            }
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).with_path(parent)?;
+            }
+            self.transfer_file(&src, &target).with_path(&src)?;
+        }
+
+        for (meta, target) in dirs.iter().rev() {
+            let mtime = FileTime::from_last_modification_time(meta);
+            set_metadata(target, FileKind::Dir, Some(meta.mode()), Some(mtime))
+                .with_path(target)?;
         }
         Ok(())
     }
 
+    /// Removes the transferred `files` from `base`. Directories are only
+    /// removed once empty, so excluded files stay in place.
+    pub fn remove(base: &Path, files: &[PackageFile]) -> Result<()> {
+        database::remove_entries(files.iter().map(|file| (base.join(&file.src), file.kind)))
+    }
+
     pub fn new(strategy: TransferStrategy) -> Self {
         Self { strategy }
     }
@@ -0,0 +1,109 @@
+use std::{
+    fmt::{self, Display},
+    path::Path,
+    process::Command,
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config,
+    error::{Error, Result, WithPath},
+};
+
+/// A point in the lifecycle of a program at which the manifest `hooks` run a
+/// script. Scripts are given as paths inside the resource directory.
+///
+/// Before hooks run before the new version is put in place, in the staging
+/// directory that becomes its resource directory, and ahead of the install
+/// script. After hooks run once it is in place, in its resource directory, and
+/// a failing one rolls the installation back. Removal hooks run in the
+/// resource directory before the files are removed, and in the install root
+/// after, when a copy of the script is run.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Hook {
+    PreInstall,
+    PostInstall,
+    PreUpgrade,
+    PostUpgrade,
+    PreRemove,
+    PostRemove,
+}
+
+impl Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Hook::PreInstall => "pre_install",
+            Hook::PostInstall => "post_install",
+            Hook::PreUpgrade => "pre_upgrade",
+            Hook::PostUpgrade => "post_upgrade",
+            Hook::PreRemove => "pre_remove",
+            Hook::PostRemove => "post_remove",
+        })
+    }
+}
+
+/// The program a script runs for. Scripts get it in their environment:
+///
+/// | variable           | value                                                          |
+/// |--------------------|----------------------------------------------------------------|
+/// | `RES`              | the resource directory of the version, gone for `post_remove`  |
+/// | `EBPM_STAGING`     | the staging directory, only set before the version is in place |
+/// | `EBPM_HOME`        | the install root                                               |
+/// | `EBPM_HOOK`        | the hook, or `install_script` and `remove_script`              |
+/// | `EBPM_NAME`        | the program name                                               |
+/// | `EBPM_VERSION`     | the version being installed or removed                         |
+/// | `EBPM_OLD_VERSION` | the version being replaced, only set for upgrades              |
+pub(crate) struct Context<'a> {
+    pub root: &'a Path,
+    /// The final resource directory of the version.
+    pub res: &'a Path,
+    pub name: &'a str,
+    pub version: &'a Version,
+    pub old_version: Option<&'a Version>,
+}
+
+impl Context<'_> {
+    /// Runs `script` with `sh` in `dir` and waits for it. A non-zero exit fails
+    /// with [`Error::Script`].
+    pub(crate) fn run(&self, hook: &str, script: &Path, dir: &Path) -> Result<()> {
+        self.execute(hook, script, dir, None)
+    }
+
+    /// Runs `script` like [`Context::run`] in `staging`, before the version
+    /// is moved to its resource directory.
+    pub(crate) fn run_staged(&self, hook: &str, script: &Path, staging: &Path) -> Result<()> {
+        self.execute(hook, script, staging, Some(staging))
+    }
+
+    fn execute(&self, hook: &str, script: &Path, dir: &Path, staging: Option<&Path>) -> Result<()> {
+        let mut command = Command::new("sh");
+        command
+            .current_dir(dir)
+            .arg(script)
+            .env("RES", self.res)
+            .env(config::HOME_VAR, self.root)
+            .env("EBPM_HOOK", hook)
+            .env("EBPM_NAME", self.name)
+            .env("EBPM_VERSION", self.version.to_string())
+            .env_remove("EBPM_STAGING")
+            .env_remove("EBPM_OLD_VERSION");
+        if let Some(staging) = staging {
+            command.env("EBPM_STAGING", staging);
+        }
+        if let Some(old_version) = self.old_version {
+            command.env("EBPM_OLD_VERSION", old_version.to_string());
+        }
+
+        let status = command.status().with_path(script)?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(Error::Script {
+                script: format!("{} ({})", script.display(), hook),
+                status,
+            }),
+        }
+    }
+}
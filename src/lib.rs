@@ -0,0 +1,276 @@
+//! ebpm - a small package manager installing programs into a per-user root.
+//!
+//! Everything goes through an [`Ebpm`] context, which owns the install layout:
+//!
+//! ```no_run
+//! use ebpm::{package::Package, Ebpm};
+//!
+//! let ebpm = Ebpm::new("/tmp/ebpm-root");
+//! ebpm.install(&[Package::new("hello.ebpm.zip".into())], &[], false, false)?;
+//! for program in ebpm.list()? {
+//!     println!("{} {}", program.name, program.version);
+//! }
+//! # Ok::<(), ebpm::error::Error>(())
+//! ```
+
+pub mod archive;
+pub mod cache;
+pub mod config;
+pub mod database;
+mod dependency;
+pub mod error;
+pub mod event;
+pub mod files;
+pub mod filetransfer;
+pub mod hook;
+pub mod package;
+pub mod program;
+pub mod repository;
+pub mod signature;
+mod tar;
+mod transaction;
+pub mod util;
+mod zip;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use archive::BuildOptions;
+use config::{Config, Prompt};
+use database::Difference;
+use error::{Error, Result};
+use event::Event;
+use filetransfer::TransferStrategy;
+use package::{Package, UnpackedPackage};
+use program::{Program, ProgramResources};
+use semver::Version;
+
+/// An ebpm installation rooted at a single directory holding the `res`
+/// directory and the installed program records, with launchers written to
+/// the `exe` directory.
+pub struct Ebpm {
+    root: PathBuf,
+    exe_dir: PathBuf,
+    temp_dir: PathBuf,
+    transfer: TransferStrategy,
+    prompt: Prompt,
+    cancelled: Arc<AtomicBool>,
+    reporter: Arc<dyn Fn(&Event) + Send + Sync>,
+}
+
+impl Ebpm {
+    /// Uses `root` as install root with the default layout. Nothing is created
+    /// until something is installed.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            exe_dir: root.join("exe"),
+            temp_dir: PathBuf::from("/var/tmp"),
+            transfer: TransferStrategy::Copy,
+            prompt: Prompt::Ask,
+            cancelled: Arc::default(),
+            reporter: Arc::new(|_| {}),
+            root,
+        }
+    }
+
+    /// Applies the config file and the `EBPM_HOME` override on top of the
+    /// default install root, `~/Applications`.
+    pub fn from_env() -> Result<Self> {
+        Self::from_config(Config::load(&Config::path()?)?)
+    }
+
+    pub fn from_config(config: Config) -> Result<Self> {
+        let root = env::var_os(config::HOME_VAR)
+            .map(PathBuf::from)
+            .or(config.root)
+            .or_else(|| env::home_dir().map(|home| home.join("Applications")))
+            .ok_or_else(|| Error::NotFound("Home directory is not known".into()))?;
+
+        let mut ebpm = Self::new(root);
+        if let Some(exe_dir) = config.exe_dir {
+            ebpm.exe_dir = exe_dir;
+        }
+        if let Some(temp_dir) = config.temp_dir {
+            ebpm.temp_dir = temp_dir;
+        }
+        ebpm.transfer = config.transfer.unwrap_or(ebpm.transfer);
+        ebpm.prompt = config.prompt.unwrap_or(ebpm.prompt);
+        Ok(ebpm)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory the program launchers are written to.
+    pub fn exe_dir(&self) -> &Path {
+        &self.exe_dir
+    }
+
+    /// Directory packages are unpacked to before installation.
+    pub fn temp_dir(&self) -> &Path {
+        &self.temp_dir
+    }
+
+    /// Strategy for installing unpacked package directories.
+    pub fn transfer(&self) -> TransferStrategy {
+        self.transfer
+    }
+
+    pub fn prompt(&self) -> Prompt {
+        self.prompt
+    }
+
+    /// Flag that cancels a running installation once set, for example from a
+    /// signal handler. The installation is rolled back and fails with
+    /// [`Error::Interrupted`]; the flag stays set until it is cleared.
+    pub fn cancellation(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    /// Passes the progress and warnings of every later operation to
+    /// `reporter`. Nothing is reported by default.
+    pub fn set_reporter(&mut self, reporter: impl Fn(&Event) + Send + Sync + 'static) {
+        self.reporter = Arc::new(reporter);
+    }
+
+    pub(crate) fn report(&self, event: Event) {
+        (self.reporter)(&event)
+    }
+
+    /// The effective value of a config key, see [`config::KEYS`].
+    pub fn setting(&self, key: &str) -> Result<String> {
+        Ok(match key {
+            "root" => self.root.display().to_string(),
+            "exe_dir" => self.exe_dir.display().to_string(),
+            "temp_dir" => self.temp_dir.display().to_string(),
+            "transfer" => self.transfer.to_string(),
+            "prompt" => self.prompt.to_string(),
+            other => return Err(config::unknown_key(other)),
+        })
+    }
+
+    /// The paths of the active version of `name`, found by following the
+    /// link to its record.
+    pub fn resources(&self, name: &str) -> ProgramResources {
+        let active = ProgramResources::new(&self.root, &self.exe_dir, name, None);
+        let version = fs::read_link(&active.manifest).ok().and_then(|target| {
+            let stem = target.file_stem()?.to_str()?;
+            Version::parse(stem.strip_prefix(name)?.strip_prefix('@')?).ok()
+        });
+        match version {
+            Some(version) => self.version_resources(name, &version),
+            None => active,
+        }
+    }
+
+    /// The paths of a specific version of `name`.
+    pub fn version_resources(&self, name: &str, version: &Version) -> ProgramResources {
+        ProgramResources::new(&self.root, &self.exe_dir, name, Some(version))
+    }
+
+    /// Installs packages and their dependencies in order, see [`Package::install_all`].
+    pub fn install(
+        &self,
+        packages: &[Package],
+        dependencies: &[Package],
+        force: bool,
+        keep: bool,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        Package::install_all(self, packages, dependencies, force, keep)
+    }
+
+    /// Installs an already unpacked package directory.
+    pub fn install_unpacked(
+        &self,
+        package: &UnpackedPackage,
+        strategy: TransferStrategy,
+        keep: bool,
+    ) -> Result<Vec<String>> {
+        package.install(self, strategy, keep)
+    }
+
+    /// Upgrades the program of `package`, see [`Package::upgrade`].
+    pub fn upgrade(&self, package: &Package, force: bool, keep: bool) -> Result<Vec<String>> {
+        package.upgrade(self, force, keep)
+    }
+
+    /// Removes every version of a program, or only the given one for
+    /// `name@version`. The active version is only removed along with all others.
+    pub fn remove(&self, spec: &str) -> Result<()> {
+        let (name, version) = program::parse_spec(spec)?;
+        let versions = Program::versions(self, name)?;
+        let selected: Vec<&Program> = versions
+            .iter()
+            .filter(|it| {
+                version
+                    .as_ref()
+                    .is_none_or(|version| it.version == *version)
+            })
+            .collect();
+
+        if selected.is_empty() {
+            return Err(Error::NotFound(format!(
+                "Program '{}' doesn't exist!",
+                spec
+            )));
+        }
+        if selected.len() < versions.len() && selected.iter().any(|it| it.is_active(self)) {
+            return Err(Error::Conflict(format!(
+                "{} is the active version, switch to another one first",
+                spec
+            )));
+        }
+        selected.iter().try_for_each(|it| it.remove(self))
+    }
+
+    /// Points the launcher of `name` at an installed `version`.
+    pub fn switch(&self, name: &str, version: &Version) -> Result<()> {
+        let resources = self.version_resources(name, version);
+        if !resources.manifest.is_file() {
+            return Err(Error::NotFound(format!(
+                "{} {} is not installed",
+                name, version
+            )));
+        }
+        resources.activate()
+    }
+
+    /// Compares an installed program against the files recorded at installation.
+    pub fn verify(&self, name: &str) -> Result<Vec<Difference>> {
+        Program::load(self, name)?.verify(self)
+    }
+
+    /// Restores an installed program from its cached package, see [`Program::repair`].
+    pub fn repair(&self, name: &str) -> Result<Vec<Difference>> {
+        Program::load(self, name)?.repair(self)
+    }
+
+    /// Restores the last replaced version of a program, see [`Program::rollback`].
+    pub fn rollback(&self, name: &str) -> Result<Program> {
+        Program::rollback(self, name)
+    }
+
+    pub fn program(&self, name: &str) -> Result<Program> {
+        Program::load(self, name)
+    }
+
+    /// Every installed program, sorted by name.
+    pub fn list(&self) -> Result<Vec<Program>> {
+        Program::installed_programs(self)
+    }
+
+    pub fn run(&self, name: &str, args: &[String]) -> Result<ExitStatus> {
+        Program::load(self, name)?.run(self, args)
+    }
+
+    /// Packs the package directory `dir` into `<dir>/<name>.ebpm.<extension>`.
+    pub fn build(&self, dir: &Path, options: &BuildOptions) -> Result<Package> {
+        UnpackedPackage::try_from(dir)?.pack(options)
+    }
+}
@@ -1,35 +1,93 @@
-mod filetransfer;
-mod package;
-mod program;
-mod util;
-mod zip;
-
 use std::{
     env,
     fs::{self},
-    io::Write,
-    path::{Path, PathBuf},
+    io::{self, Write},
+    path::Path,
     process::{exit, Command},
+    sync::atomic::Ordering,
 };
 
-use filetransfer::TransferStrategy;
-
-use package::{Package, UnpackedPackage};
-use program::{Manifest, Program};
-use util::{request_yes_or_no, GetSize};
-
-use crate::program::ProgramResources;
+use ebpm::{
+    archive::{BuildOptions, Compression, PackageFormat},
+    cache,
+    config::{self, Config, Prompt},
+    error::{Error, Result, WithPath},
+    filetransfer::TransferStrategy,
+    package::{Package, UnpackedPackage, CONFIG_SUFFIX},
+    program::{self, Manifest},
+    repository::{self, Repository},
+    signature,
+    util::{request_yes_or_no, GetSize},
+    Ebpm,
+};
+use semver::Version;
 
 fn print_help() {
     println!("Usage: ebpm [command] [program_name]");
     println!();
     println!("Commands:");
     println!("    new [program_name] - create a new program file");
-    println!("    install [program_name] - install a program");
-    println!("    remove [program_name] - remove a program");
+    println!("    install [program_name] - install a program from the configured repositories");
+    println!("        -f [packages] - install packages and their dependencies in order");
+    println!("        --force - install even if dependencies are unresolved");
+    println!("        --keep - keep the replaced version installed next to the new one");
+    println!("    install - - install a package read from stdin");
+    println!("        --signature [file] - the .sig of the package, which a package read");
+    println!("            from stdin has none next to, as required by 'key policy require'");
+    println!(
+        "    upgrade [--force] [--keep] [--signature file] [package|-] - upgrade a program if"
+    );
+    println!("        the package is newer");
+    println!("    remove [program_name[@version]] - remove a program or one of its versions");
+    println!("    switch [program_name] [version] - make an installed version the active one");
+    println!("    rollback [program_name] - restore the version replaced by the last upgrade");
     println!("    list - list all installed programs");
-    println!("    run [program_name] - run a program");
-    println!("    build - build a package");
+    println!("    files [program_name] - list files installed by a program");
+    println!("    owns [path] - find the program that installed a file");
+    println!("    verify [--repair] [program_name...] - check installed files against the");
+    println!("        recorded hashes and modes, all programs by default; --repair restores");
+    println!("        missing and modified files from the cached package");
+    println!("    run [program_name[@version]] - run the active or the given version of a program");
+    println!("    build [--sign key_file] - build a package, optionally signing it");
+    println!("        --format [zip|tar.gz|tar.xz|tar.zst] - archive format, zip by default");
+    println!(
+        "        --compression [store|deflate|bzip2|zstd] - zip compression, bzip2 by default"
+    );
+    println!("        --level [level] - compression level");
+    println!("        --reproducible - normalize timestamps, ownership, modes and entry order,");
+    println!("            implied when SOURCE_DATE_EPOCH is set, which gives the timestamp");
+    println!("    inspect [--json] [package|-] - show the manifest and entries of a package");
+    println!("    search [term] - search the configured repositories");
+    println!("    repo add|remove [path] - manage package repositories");
+    println!("    repo list - list package repositories");
+    println!("    repo index [path] - generate index.json for a repository");
+    println!("    cache list - list the packages kept from installations");
+    println!("    cache clean - remove cached packages no installed program came from");
+    println!("    cache prune --keep [count] - remove all but the newest cached versions");
+    println!("        of each program, keeping the ones in use");
+    println!("    key generate [name] - create a signing key pair in the current directory");
+    println!("    key add [name] [public_key_file] - trust a public key");
+    println!("    key remove [name] - stop trusting a public key");
+    println!("    key list - list trusted public keys");
+    println!("    key policy [warn|require] - show or set the policy for unsigned packages");
+    println!("    config list - show the effective configuration");
+    println!("    config get [key] - show a configuration value");
+    println!("    config set [key] [value] - change a configuration value");
+    println!();
+    println!("Configuration keys:");
+    println!("    root - install root, ~/Applications by default or $EBPM_HOME when set");
+    println!("    exe_dir - directory for program launchers, [root]/exe by default");
+    println!("    temp_dir - directory packages are unpacked to, /var/tmp by default");
+    println!("    transfer [copy|move] - how 'install -fa' and 'new' treat source files");
+    println!("    prompt [ask|yes|no] - answer questions interactively or automatically");
+    println!("The configuration is read from $EBPM_HOME/.config.json when EBPM_HOME is set,");
+    println!("and from ~/.config/ebpm/config.json otherwise.");
+    println!();
+    println!("Exit codes:");
+    println!("    0 success, 2 invalid arguments, 3 not found, 4 conflict,");
+    println!("    5 bad manifest, 6 bad archive, 7 integrity failure, 8 script failure,");
+    println!("    9 I/O error, 130 interrupted");
+    println!("    'ebpm run' exits with the code of the program, which may overlap these");
     println!();
     println!("Example: ebpm new my_program");
 }
@@ -41,141 +99,644 @@ fn main() {
         return;
     }
 
+    // Neither needs a valid config, so that a broken one can be repaired.
+    let result = match args[1].as_str() {
+        "help" => {
+            print_help();
+            Ok(())
+        }
+        "config" => manage_config(&args),
+        _ => Ebpm::from_env().and_then(|mut ebpm| {
+            ebpm.set_reporter(|event| println!("{}", event));
+            run_command(&ebpm, &args)
+        }),
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        exit(err.exit_code());
+    }
+}
+
+fn run_command(ebpm: &Ebpm, args: &[String]) -> Result<()> {
     match args[1].as_str() {
-        "new" => new_program(&args),
-        "help" => print_help(),
-        "install" => install_program(&args),
-        "remove" => remove_program(&args),
-        "run" => run_program(&args),
-        "list" => print_list(),
-        "build" => build_package(),
+        "new" => new_program(ebpm, args),
+        "install" => install_program(ebpm, args),
+        "upgrade" => upgrade_program(ebpm, args),
+        "remove" => remove_program(ebpm, args),
+        "switch" => switch_version(ebpm, args),
+        "rollback" => rollback_program(ebpm, args),
+        "run" => run_program(ebpm, args),
+        "list" => print_list(ebpm),
+        "files" => print_files(ebpm, args),
+        "owns" => print_owner(ebpm, args),
+        "verify" => verify_programs(ebpm, args),
+        "build" => build_package(ebpm, args),
+        "inspect" => inspect_package(ebpm, args),
+        "search" => search_packages(ebpm, args),
+        "repo" => manage_repositories(ebpm, args),
+        "cache" => manage_cache(ebpm, args),
+        "key" => manage_keys(ebpm, args),
 
         _ => {
-            println!("Invalid arguments");
             print_help();
-            exit(-1);
+            Err(Error::Usage("Invalid arguments".into()))
         }
     }
 }
 
-fn build_package() {
-    let package = UnpackedPackage::try_from(env::current_dir().unwrap().as_path()).unwrap();
-    package.pack();
+/// The value following `option` in `args`, if the option is given.
+fn option_value<'a>(args: &'a [String], option: &str, value: &str) -> Result<Option<&'a str>> {
+    match args.iter().position(|it| it.as_str() == option) {
+        None => Ok(None),
+        Some(index) => args
+            .get(index + 1)
+            .map(|it| Some(it.as_str()))
+            .ok_or_else(|| Error::Usage(format!("{} requires {}", option, value))),
+    }
+}
+
+/// The arguments following the command, without the `flags` and the
+/// `options` taking a value, along with their values.
+fn operands(args: &[String], flags: &[&str], options: &[&str]) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut rest = args.iter().skip(2);
+    while let Some(arg) = rest.next() {
+        if options.contains(&arg.as_str()) {
+            rest.next();
+        } else if !flags.contains(&arg.as_str()) {
+            operands.push(arg.clone());
+        }
+    }
+    operands
+}
+
+fn build_package(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let key = match option_value(args, "--sign", "a key file")? {
+        None => None,
+        Some(key) => Some(fs::canonicalize(key).with_path(key)?),
+    };
+    let options = BuildOptions {
+        format: option_value(args, "--format", "a package format")?
+            .map(PackageFormat::try_from)
+            .transpose()?
+            .unwrap_or_default(),
+        compression: option_value(args, "--compression", "a compression method")?
+            .map(Compression::try_from)
+            .transpose()?,
+        level: option_value(args, "--level", "a compression level")?
+            .map(|level| {
+                level
+                    .parse()
+                    .map_err(|_| Error::Usage(format!("'{}' is not a compression level", level)))
+            })
+            .transpose()?,
+        reproducible: match args.iter().any(|it| it == "--reproducible")
+            || env::var_os("SOURCE_DATE_EPOCH").is_some()
+        {
+            true => Some(BuildOptions::source_date_epoch()?),
+            false => None,
+        },
+    };
+
+    let dir = env::current_dir().with_path(".")?;
+    let package = ebpm.build(&dir, &options)?;
+
+    match key {
+        Some(key) => {
+            signature::sign(&package.path, &key)?;
+            println!("Signed {}", package.path.display());
+        }
+        // A signature left over from a previous build would no longer match.
+        None => _ = fs::remove_file(signature::signature_path(&package.path)),
+    }
+    Ok(())
+}
+
+fn inspect_package(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let json = args.iter().any(|it| it.as_str() == "--json");
+    let path = args
+        .iter()
+        .skip(2)
+        .find(|it| it.as_str() != "--json")
+        .ok_or_else(|| Error::Usage("package is not specified".into()))?;
+    let inspection = package_file(ebpm, path, None)?.inspect()?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&inspection).unwrap());
+        return Ok(());
+    }
+
+    let manifest = &inspection.manifest;
+    println!("{} {}", manifest.name, manifest.version);
+    if !manifest.description.is_empty() {
+        println!("{}", manifest.description);
+    }
+    println!();
+    println!(
+        "{:<16}{}, {} bytes",
+        "Format:", inspection.format, inspection.size
+    );
+    println!("{:<16}{}", "Command:", manifest.cmd);
+    println!("{:<16}{}", "Install script:", manifest.install_script);
+    println!("{:<16}{}", "Remove script:", manifest.remove_script);
+    let hooks: Vec<String> = manifest
+        .hooks
+        .iter()
+        .map(|(hook, script)| format!("{}: {}", hook, script))
+        .collect();
+    println!("{:<16}{}", "Hooks:", hooks.join(", "));
+    let dependencies: Vec<String> = manifest
+        .dependencies
+        .iter()
+        .map(|(name, req)| format!("{} {}", name, req))
+        .collect();
+    println!("{:<16}{}", "Dependencies:", dependencies.join(", "));
+    println!();
+
+    println!("{:<8}{:>12}{:>12}  Name:", "Mode:", "Size:", "Packed:");
+    inspection.entries.iter().for_each(|entry| {
+        let mode = entry.mode.map_or("-".into(), |it| format!("{:04o}", it));
+        let packed = entry
+            .compressed_size
+            .map_or("-".into(), |it| it.to_string());
+        match &entry.target {
+            Some(target) => println!(
+                "{:<8}{:>12}{:>12}  {} -> {}",
+                mode, entry.size, packed, entry.name, target
+            ),
+            None => println!(
+                "{:<8}{:>12}{:>12}  {}",
+                mode, entry.size, packed, entry.name
+            ),
+        }
+    });
+
+    inspection
+        .warnings
+        .iter()
+        .for_each(|warning| println!("Warning: {}", warning));
+    Ok(())
 }
 
-fn new_program(args: &Vec<String>) {
+fn new_program(ebpm: &Ebpm, args: &[String]) -> Result<()> {
     if args.len() != 3 {
-        println!("Error: incrorrect program name specified");
-        exit(-1)
+        return Err(Error::Usage("incrorrect program name specified".into()));
     }
+    program::validate_name(&args[2])?;
 
-    let manifest = Manifest::new(args[2].clone(), &Vec::new());
+    let manifest = Manifest::new(args[2].clone(), Version::new(0, 1, 0), &[]);
     let json = serde_json::to_string_pretty(&manifest).unwrap();
 
-    {
-        std::fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open("manifest.ebpm.json")
-            .unwrap_or_else(|_e| fs::File::create("manifest.ebpm.json").unwrap())
-            .write(json.as_str().as_bytes())
-            .unwrap();
-    }
+    fs::File::create("manifest.ebpm.json")
+        .and_then(|mut file| file.write_all(json.as_bytes()))
+        .with_path("manifest.ebpm.json")?;
 
     Command::new("micro")
         .arg("manifest.ebpm.json")
-        .spawn()
-        .unwrap()
-        .wait()
-        .unwrap();
+        .status()
+        .with_path("micro")?;
 
-    let install = request_yes_or_no("Do you want to install program?");
-
-    if install {
-        let strategy = match request_yes_or_no("Do you want to remove source package files?") {
-            true => TransferStrategy::Move,
-            false => TransferStrategy::Copy,
+    if confirm(ebpm, "Do you want to install program?") {
+        // Source files are only removed when asked, never by an automatic answer.
+        let strategy = match ebpm.prompt() {
+            Prompt::Ask => match request_yes_or_no("Do you want to remove source package files?") {
+                true => TransferStrategy::Move,
+                false => TransferStrategy::Copy,
+            },
+            _ => ebpm.transfer(),
         };
 
-        UnpackedPackage::try_from(env::current_dir().unwrap().as_path())
-            .unwrap()
-            .install(strategy)
-            .unwrap()
+        let dir = env::current_dir().with_path(".")?;
+        cancel_on_interrupt(ebpm);
+        let package = UnpackedPackage::try_from(dir.as_path())?;
+        warn_kept(&ebpm.install_unpacked(&package, strategy, false)?);
     }
+    Ok(())
 }
 
-fn install_program(args: &Vec<String>) {
-   
+/// Lets Ctrl-C roll back a running installation instead of killing ebpm halfway.
+fn cancel_on_interrupt(ebpm: &Ebpm) {
+    let cancelled = ebpm.cancellation();
+    if let Err(err) = ctrlc::set_handler(move || cancelled.store(true, Ordering::SeqCst)) {
+        eprintln!("Failed to install interrupt handler: {}", err);
+    }
+}
 
-    if args.iter().filter(|it| it.as_str() == "-fa").count() > 0 {
-        args.iter()
-            .skip(2)
-            .filter(|t| t.as_str() != "-fa")
-            .for_each(|it| {
-                UnpackedPackage::try_from(Path::new(it))
-                    .unwrap()
-                    .install(TransferStrategy::Copy)
-                    .unwrap();
-            });
-        return;
+/// Points out the config files an installation kept instead of replacing.
+fn warn_kept(kept: &[String]) {
+    for file in kept {
+        println!(
+            "Warning: kept the modified config file {}, merge {}{} by hand",
+            file, file, CONFIG_SUFFIX
+        );
     }
+}
 
-    if args.iter().filter(|it| it.as_str() == "-f").count() > 0 {
-        args.iter()
+fn install_program(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    cancel_on_interrupt(ebpm);
+    let keep = args.iter().any(|it| it.as_str() == "--keep");
+    if args.iter().any(|it| it.as_str() == "-fa") {
+        return args
+            .iter()
             .skip(2)
-            .filter(|t| t.as_str() != "-f")
-            .map(|it| Package::new(it.into()))
-            .for_each(|it| {
-                println!("installing {}", it.path.file_name().unwrap().to_str().unwrap());
-                it.install().unwrap();
-                println!("Installing finished");
+            .filter(|t| t.as_str() != "-fa" && t.as_str() != "--keep")
+            .try_for_each(|it| {
+                let package = UnpackedPackage::try_from(Path::new(it))?;
+                warn_kept(&ebpm.install_unpacked(&package, ebpm.transfer(), keep)?);
+                Ok(())
             });
     }
 
+    let force = args.iter().any(|it| it.as_str() == "--force");
+    let from_files = args.iter().any(|it| it.as_str() == "-f");
+    let signature = option_value(args, "--signature", "a signature file")?;
+    let targets = operands(args, &["-f", "--force", "--keep"], &["--signature"]);
+
+    let from_stdin = !targets.is_empty() && targets.iter().all(|it| it == "-");
+    let (packages, dependencies) = match from_files || from_stdin {
+        true => (
+            targets
+                .iter()
+                .map(|it| package_file(ebpm, it, signature))
+                .collect::<Result<_>>()?,
+            Vec::new(),
+        ),
+        false => repository::collect(ebpm, &targets)?,
+    };
+
+    ebpm.install(&packages, &dependencies, force, keep)?
+        .iter()
+        .for_each(|(_, kept)| warn_kept(kept));
+    Ok(())
 }
 
-fn remove_program(args: &Vec<String>) {
-    Program::load(&args[2]).remove();
+fn search_packages(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let term = args.get(2).map(String::as_str).unwrap_or("");
+    let found = repository::search(ebpm, term);
+    if found.is_empty() {
+        println!("No packages found");
+    }
+    found.iter().for_each(|entry| {
+        println!(
+            "--- {:<40}{:<16}{}",
+            entry.name,
+            entry.version.to_string(),
+            entry.description
+        )
+    });
+    Ok(())
+}
+
+fn manage_repositories(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let location = args.get(3).map(String::as_str);
+    match (args.get(2).map(String::as_str), location) {
+        (Some("add"), Some(location)) => Repository::add(ebpm, location),
+        (Some("remove"), Some(location)) => Repository::remove(ebpm, location),
+        (Some("index"), location) => Repository::open(location.unwrap_or("."))
+            .generate_index()
+            .map(|index| println!("Indexed {} packages", index.packages.len())),
+        (Some("list"), _) => {
+            Repository::configured(ebpm)
+                .iter()
+                .for_each(|it| println!("--- {}", it.root.display()));
+            Ok(())
+        }
+        _ => Err(Error::Usage("Invalid arguments".into())),
+    }
+}
+
+fn upgrade_program(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    cancel_on_interrupt(ebpm);
+    let force = args.iter().any(|it| it.as_str() == "--force");
+    let keep = args.iter().any(|it| it.as_str() == "--keep");
+    let signature = option_value(args, "--signature", "a signature file")?;
+
+    operands(args, &["--force", "--keep"], &["--signature"])
+        .iter()
+        .try_for_each(|it| {
+            warn_kept(&ebpm.upgrade(&package_file(ebpm, it, signature)?, force, keep)?);
+            Ok(())
+        })
 }
 
-fn print_list() {
-    let mut path = std::env::home_dir().unwrap();
-    path.push("Applications");
-    let files = fs::read_dir(path).unwrap();
+fn rollback_program(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let name = program_name(args)?;
+    let current = ebpm.program(name)?.version;
+    let restored = ebpm.rollback(name)?;
+    println!("Rolled back {} {} -> {}", name, current, restored.version);
+    Ok(())
+}
 
-    print!("Installed programs:");
-    for i in 0..44 - ("Installed programs:".len()) {
-        print!(" ");
+fn switch_version(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let (Some(name), Some(version)) = (args.get(2), args.get(3)) else {
+        return Err(Error::Usage(
+            "program name and version are not specified".into(),
+        ));
+    };
+    let version = Version::parse(version)
+        .map_err(|_| Error::Usage(format!("'{}' is not a valid version", version)))?;
+    ebpm.switch(name, &version)?;
+    println!("{} now runs version {}", name, version);
+    Ok(())
+}
+
+/// The package file `path`, where `-` reads the package from stdin.
+fn package_file(ebpm: &Ebpm, path: &str, signature: Option<&str>) -> Result<Package> {
+    match (path, signature) {
+        ("-", None) => Package::from_reader(ebpm, io::stdin().lock()),
+        ("-", Some(signature)) => {
+            Package::from_reader(ebpm, io::stdin().lock())?.with_signature(Path::new(signature))
+        }
+        (_, Some(_)) => Err(Error::Usage(
+            "--signature only applies to a package read from stdin".into(),
+        )),
+        (path, None) => Ok(Package::new(path.into())),
     }
-    println!("Size:");
-    files
-        .map(|it| it.unwrap().file_name().into_string().unwrap())
-        .filter(|it| it.ends_with(".json"))
-        .for_each(|it| {
-            let program = Program::load(it.strip_suffix(".json").unwrap());
-            let resourse = ProgramResources::from(&program);
+}
 
-            print!("--- {}", program.name);
+fn program_name(args: &[String]) -> Result<&str> {
+    args.get(2)
+        .map(String::as_str)
+        .ok_or_else(|| Error::Usage("program name is not specified".into()))
+}
+
+fn remove_program(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    ebpm.remove(program_name(args)?)
+}
+
+fn print_list(ebpm: &Ebpm) -> Result<()> {
+    println!("{:<44}{:<16}Size:", "Installed programs:", "Version:");
+    ebpm.list()?.into_iter().for_each(|program| {
+        let resourse = program.resources(ebpm);
+
+        let name = match (program.auto_installed, program.is_active(ebpm)) {
+            (_, false) => format!("{} (inactive)", program.name),
+            (true, true) => format!("{} (dependency)", program.name),
+            (false, true) => program.name.clone(),
+        };
+        print!("--- {:<40}{:<16}", name, program.version.to_string());
+        let folder_size = resourse.res_path.get_size();
+        let folder_size = if folder_size < 1024 {
+            format!("Bytes {}", folder_size)
+        } else if folder_size < 1024 * 1024 {
+            format!("KiB {:.2}", (folder_size as f64 / 1024.0))
+        } else if folder_size < 1024 * 1024 * 1024 {
+            format!("MiB {:.2}", (folder_size as f64 / 1024.0 / 1024.0))
+        } else if folder_size < 1024 * 1024 * 1024 * 1024 {
+            format!("GiB {:.2}", (folder_size as f64 / 1024.0 / 1024.0 / 1024.0))
+        } else {
+            format!("Bytes {}", folder_size)
+        };
+        println!("{}", folder_size)
+    });
+    Ok(())
+}
+
+fn run_program(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let status = ebpm.run(program_name(args)?, &args[3..])?;
+    // The program's own exit code is passed through unchanged.
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(code) => exit(code),
+        None => Err(Error::Script {
+            script: args[2].clone(),
+            status,
+        }),
+    }
+}
+
+fn print_files(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let program = ebpm.program(program_name(args)?)?;
+    let resources = program.resources(ebpm);
+    if program.installed.is_empty() {
+        println!(
+            "Warning: no file database recorded for '{}', it was installed by an older ebpm",
+            program.name
+        );
+    }
+
+    println!("{}", resources.exe_path.display());
+    println!("{}", resources.manifest.display());
+    program
+        .installed
+        .iter()
+        .for_each(|file| println!("{}", resources.res_path.join(&file.path).display()));
+    Ok(())
+}
 
-            for _ in 0..40 - program.name.len() {
-                print!(" ")
+fn print_owner(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let Some(path) = args.get(2) else {
+        return Err(Error::Usage("path is not specified".into()));
+    };
+
+    // Only the parent is resolved so that symlinks installed by a program stay recognizable.
+    let path = Path::new(path);
+    let path = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => fs::canonicalize(if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        })
+        .map(|parent| parent.join(name)),
+        _ => fs::canonicalize(path),
+    }
+    .with_path(path)?;
+
+    let owner = ebpm.list()?.into_iter().find(|program| {
+        let resources = program.resources(ebpm);
+        ((path == resources.exe_path || path == resources.active) && program.is_active(ebpm))
+            || path == resources.manifest
+            || path
+                .strip_prefix(&resources.res_path)
+                .is_ok_and(|relative| {
+                    program
+                        .installed
+                        .iter()
+                        .any(|file| Path::new(&file.path) == relative)
+                })
+    });
+
+    match owner {
+        Some(program) => {
+            println!(
+                "{} is owned by {} {}",
+                path.display(),
+                program.name,
+                program.version
+            );
+            Ok(())
+        }
+        None => Err(Error::NotFound(format!(
+            "{} is not owned by any installed program",
+            path.display()
+        ))),
+    }
+}
+
+fn verify_programs(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let repair = args[2..].iter().any(|it| it == "--repair");
+    let mut names: Vec<String> = args[2..]
+        .iter()
+        .filter(|it| *it != "--repair")
+        .cloned()
+        .collect();
+    if names.is_empty() {
+        names = ebpm
+            .list()?
+            .into_iter()
+            .map(|it| match it.is_active(ebpm) {
+                true => it.name,
+                false => format!("{}@{}", it.name, it.version),
+            })
+            .collect();
+    }
+
+    let mut failed = Vec::new();
+    for name in names.iter() {
+        let program = ebpm.program(name)?;
+        if program.installed.is_empty() {
+            println!(
+                "{}: skipped, no file database recorded as it was installed by an older ebpm",
+                name
+            );
+            continue;
+        }
+
+        let differences = match repair {
+            true => program.repair(ebpm),
+            false => program.verify(ebpm),
+        };
+        let differences = match differences {
+            Ok(differences) => differences,
+            Err(err) => {
+                println!("{}: {}", name, err);
+                failed.push(name.as_str());
+                continue;
             }
-            let folder_size = resourse.res_path.get_size();
-            let folder_size = if folder_size < 1024 {
-                format!("Bytes {}", folder_size)
-            } else if folder_size < 1024 * 1024 {
-                format!("KiB {:.2}", (folder_size as f64 / 1024.0))
-            } else if folder_size < 1024 * 1024 * 1024 {
-                format!("MiB {:.2}", (folder_size as f64 / 1024.0 / 1024.0))
-            } else if folder_size < 1024 * 1024 * 1024 * 1024 {
-                format!("GiB {:.2}", (folder_size as f64 / 1024.0 / 1024.0 / 1024.0))
-            } else {
-                format!("Bytes {}", folder_size)
+        };
+
+        if differences.is_empty() {
+            println!("{}: OK", name);
+            continue;
+        }
+        println!("{}:", name);
+        differences
+            .iter()
+            .for_each(|difference| println!("    {}", difference));
+        failed.push(name.as_str());
+    }
+
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(Error::Integrity {
+            path: ebpm.root().to_path_buf(),
+            message: format!("{} differ from the recorded state", failed.join(", ")),
+        }),
+    }
+}
+
+fn manage_cache(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let removed = match args.get(2).map(String::as_str) {
+        Some("list") => {
+            println!(
+                "{:<44}{:<16}{:<16}Hash:",
+                "Cached packages:", "Version:", "Size:"
+            );
+            cache::list(ebpm)?.iter().for_each(|package| {
+                let name = match package.in_use {
+                    true => format!("{} (in use)", package.entry.name),
+                    false => package.entry.name.clone(),
+                };
+                println!(
+                    "--- {:<40}{:<16}{:<16}{}",
+                    name,
+                    package.entry.version.to_string(),
+                    package.entry.size,
+                    &package.entry.sha256[..16]
+                );
+            });
+            return Ok(());
+        }
+        Some("clean") => cache::clean(ebpm)?,
+        Some("prune") => {
+            let keep = option_value(args, "--keep", "count")?
+                .ok_or_else(|| Error::Usage("--keep is not specified".into()))?;
+            let keep = keep
+                .parse()
+                .map_err(|_| Error::Usage(format!("'{}' is not a valid count", keep)))?;
+            cache::prune(ebpm, keep)?
+        }
+        _ => return Err(Error::Usage("Invalid arguments".into())),
+    };
+
+    removed
+        .iter()
+        .for_each(|package| println!("Removed {} {}", package.entry.name, package.entry.version));
+    println!("Removed {} cached packages", removed.len());
+    Ok(())
+}
+
+fn manage_keys(ebpm: &Ebpm, args: &[String]) -> Result<()> {
+    let arg = |index: usize| args.get(index).map(String::as_str);
+
+    match (arg(2), arg(3), arg(4)) {
+        (Some("generate"), Some(name), _) => signature::generate(name).map(|(secret, public)| {
+            println!("Secret key written to {}", secret);
+            println!("Public key written to {}", public);
+        }),
+        (Some("add"), Some(name), Some(file)) => signature::add(ebpm, name, Path::new(file)),
+        (Some("remove"), Some(name), _) => signature::remove(ebpm, name),
+        (Some("list"), _, _) => signature::list(ebpm).map(|keys| {
+            keys.iter()
+                .for_each(|(name, key)| println!("--- {:<40}{}", name, hex::encode(key.to_bytes())))
+        }),
+        (Some("policy"), None, _) => {
+            match signature::Policy::load(ebpm) {
+                signature::Policy::Warn => println!("warn"),
+                signature::Policy::Require => println!("require"),
             };
-            println!("{}", folder_size)
-        })
+            Ok(())
+        }
+        (Some("policy"), Some(policy), _) => {
+            signature::Policy::try_from(policy).and_then(|policy| policy.save(ebpm))
+        }
+        _ => Err(Error::Usage("Invalid arguments".into())),
+    }
+}
+
+fn confirm(ebpm: &Ebpm, request: &str) -> bool {
+    match ebpm.prompt() {
+        Prompt::Ask => request_yes_or_no(request),
+        Prompt::Yes => true,
+        Prompt::No => false,
+    }
 }
 
-fn run_program(args: &Vec<String>) {
-    Program::load(&args[2]).run()
+fn manage_config(args: &[String]) -> Result<()> {
+    let arg = |index: usize| args.get(index).map(String::as_str);
+
+    match (arg(2), arg(3), arg(4)) {
+        (Some("list"), _, _) => {
+            let ebpm = Ebpm::from_env()?;
+            config::KEYS.iter().try_for_each(|key| {
+                println!("{} = {}", key, ebpm.setting(key)?);
+                Ok(())
+            })
+        }
+        (Some("get"), Some(key), _) => {
+            println!("{}", Ebpm::from_env()?.setting(key)?);
+            Ok(())
+        }
+        (Some("set"), Some(key), Some(value)) => {
+            let path = Config::path()?;
+            let mut config = Config::load(&path).unwrap_or_else(|err| {
+                println!("Warning: {}, starting from an empty config", err);
+                Config::default()
+            });
+            config.set(key, value)?;
+            config.save(&path)
+        }
+        _ => Err(Error::Usage("Invalid arguments".into())),
+    }
 }
@@ -1,108 +1,629 @@
 use std::{
-    fs::File,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, Read},
     ops::Not,
     path::{Path, PathBuf},
-    process::Command,
-    result,
 };
 
-use tempfile::{Builder, TempDir};
+use serde::Serialize;
+use tempfile::{Builder, TempDir, TempPath};
 
 use crate::{
+    archive::{ArchiveEntry, ArchivedFile, Archiver, BuildOptions, Extractor, PackageFormat},
+    cache,
+    database::{self, FileKind, InstalledFile},
+    dependency,
+    error::{Error, Result, WithPath},
+    event::Event,
+    files::{self, FileEntry, PackageFile},
     filetransfer::{FilesTransfer, TransferStrategy},
-    program::Manifest,
-    zip::{ArchivedFile, Archiver, Extractor},
-    ProgramResources,
+    hook::{Context, Hook},
+    program::{Manifest, Program},
+    signature,
+    transaction::Transaction,
+    util::sha256_file,
+    Ebpm,
 };
 
+/// Suffix of the packaged version of a config file that was kept on upgrade.
+pub const CONFIG_SUFFIX: &str = ".ebpmnew";
+
 pub struct Package {
     pub path: PathBuf,
+    /// Deletes the buffered copy of a package read from a stream once dropped.
+    _buffer: Option<TempPath>,
+    /// Deletes the signature given for the buffered copy.
+    _signature: Option<TempPath>,
 }
 
-type Error = String;
-type Result = result::Result<(), Error>;
-
 impl Package {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path,
+            _buffer: None,
+            _signature: None,
+        }
+    }
+
+    /// Buffers a package read from `reader`, such as stdin, to a temporary
+    /// file, as signatures, manifests and contents are read from it separately.
+    pub fn from_reader(ebpm: &Ebpm, mut reader: impl Read) -> Result<Self> {
+        let mut file = Builder::new()
+            .prefix("ebpm-package")
+            .tempfile_in(ebpm.temp_dir())
+            .with_path(ebpm.temp_dir())?;
+        io::copy(&mut reader, &mut file).with_path(file.path())?;
+
+        let buffer = file.into_temp_path();
+        Ok(Self {
+            path: buffer.to_path_buf(),
+            _buffer: Some(buffer),
+            _signature: None,
+        })
     }
 
-    pub fn unpack(&self) -> (UnpackedPackage, TempDir) {
-        let dir = Builder::new().prefix("ebpm").tempdir_in("/var/tmp").unwrap();
-        Extractor::new(self.path.clone()).extract(dir.path().to_path_buf());
-        (dir.path().try_into().unwrap(), dir)
+    /// Uses `signature` as the detached signature of a package read from a
+    /// stream, which has no `.sig` file next to it.
+    pub fn with_signature(mut self, signature: &Path) -> Result<Self> {
+        if self._buffer.is_none() {
+            return Err(Error::Usage(format!(
+                "{} is not read from a stream, its signature is {}",
+                self.path.display(),
+                signature::signature_path(&self.path).display()
+            )));
+        }
+        let path =
+            TempPath::try_from_path(signature::signature_path(&self.path)).with_path(&self.path)?;
+        fs::copy(signature, &path).with_path(signature)?;
+        self._signature = Some(path);
+        Ok(self)
     }
 
-    pub fn install(&self) -> Result {
-        self.unpack().0.install(TransferStrategy::Move)
+    /// Reads the manifest straight from the archive without extracting it.
+    pub fn manifest(&self) -> Result<Manifest> {
+        self.read_manifest(&mut Extractor::open(&self.path)?)
     }
+
+    fn read_manifest(&self, extractor: &mut Extractor<File>) -> Result<Manifest> {
+        let json = extractor.read_file("manifest.ebpm.json")?;
+        serde_json::from_str(&json).map_err(|source| Error::Manifest {
+            path: self.path.join("manifest.ebpm.json"),
+            source,
+        })
+    }
+
+    /// Describes the package without extracting it.
+    pub fn inspect(&self) -> Result<Inspection> {
+        let mut extractor = Extractor::open(&self.path)?;
+        let manifest = self.read_manifest(&mut extractor)?;
+        let entries = extractor.list()?;
+
+        Ok(Inspection {
+            format: extractor.format()?,
+            size: fs::metadata(&self.path).with_path(&self.path)?.len(),
+            warnings: warnings(&manifest, &entries),
+            manifest,
+            entries,
+        })
+    }
+
+    /// Verifies the package signature, extracts it into a temporary directory
+    /// and verifies its checksums.
+    pub fn unpack(&self, ebpm: &Ebpm) -> Result<(UnpackedPackage, TempDir)> {
+        signature::verify(ebpm, &self.path)?;
+
+        let dir = Builder::new()
+            .prefix("ebpm")
+            .tempdir_in(ebpm.temp_dir())
+            .with_path(ebpm.temp_dir())?;
+        Extractor::open(&self.path)?.extract(dir.path())?;
+
+        let mut package = UnpackedPackage::try_from(dir.path())?;
+        package.temporary = true;
+        package.source = Some(self.path.clone());
+        package.verify().map_err(|err| match err {
+            Error::Integrity { message, .. } => Error::integrity(&self.path, message),
+            err => err,
+        })?;
+        Ok((package, dir))
+    }
+
+    /// Installs a set of packages, dependencies first. `dependencies` are only
+    /// installed because others depend on them, and are marked as such unless
+    /// they were installed explicitly before. With `force`, unresolved
+    /// dependencies are reported as warnings instead of aborting the installation.
+    /// With `keep`, the versions they replace stay installed.
+    ///
+    /// Returns the config files kept by [`UnpackedPackage::install`], keyed by
+    /// program name.
+    pub fn install_all(
+        ebpm: &Ebpm,
+        packages: &[Package],
+        dependencies: &[Package],
+        force: bool,
+        keep: bool,
+    ) -> Result<Vec<(String, Vec<String>)>> {
+        let unpacked: Vec<(UnpackedPackage, TempDir)> = packages
+            .iter()
+            .chain(dependencies)
+            .map(|it| it.unpack(ebpm))
+            .collect::<Result<_>>()?;
+        let manifests: Vec<&Manifest> = unpacked.iter().map(|(it, _)| it.manifest()).collect();
+
+        let resolution = dependency::resolve(ebpm, &manifests)?;
+        Self::report(ebpm, resolution.problems, force)?;
+
+        let mut kept = Vec::new();
+        for index in resolution.order {
+            let package = &unpacked[index].0;
+            let name = &package.manifest().name;
+            let automatic = index >= packages.len()
+                && (!Program::is_installed(ebpm, name)
+                    || Program::load(ebpm, name)?.auto_installed);
+
+            let version = &package.manifest().version;
+            ebpm.report(Event::Installing {
+                name: name.clone(),
+                version: version.clone(),
+            });
+            let files = package.install(ebpm, TransferStrategy::Move, keep)?;
+
+            let mut program = Program::load(ebpm, name)?;
+            program.auto_installed = automatic;
+            program.save(ebpm)?;
+            ebpm.report(Event::Installed {
+                name: name.clone(),
+                version: version.clone(),
+            });
+            if !files.is_empty() {
+                kept.push((name.clone(), files));
+            }
+        }
+        Ok(kept)
+    }
+
+    /// Fails on unresolved dependency `problems`, or only reports them as
+    /// warnings with `force`.
+    fn report(ebpm: &Ebpm, problems: Vec<String>, force: bool) -> Result<()> {
+        if problems.is_empty() {
+            return Ok(());
+        }
+        if force {
+            problems
+                .into_iter()
+                .for_each(|problem| ebpm.report(Event::Warning(problem)));
+            return Ok(());
+        }
+        Err(Error::Conflict(format!(
+            "Unresolved dependencies, nothing was installed (use --force to override): {}",
+            problems.join("; ")
+        )))
+    }
+
+    /// Installs this package over the installed version of its program,
+    /// refusing downgrades unless `force` is set. Returns the kept config
+    /// files, see [`UnpackedPackage::install`].
+    pub fn upgrade(&self, ebpm: &Ebpm, force: bool, keep: bool) -> Result<Vec<String>> {
+        let (package, _dir) = self.unpack(ebpm)?;
+        let manifest = package.manifest();
+        Self::report(
+            ebpm,
+            dependency::resolve(ebpm, &[manifest])?.problems,
+            force,
+        )?;
+
+        let mut auto_installed = false;
+        if Program::is_installed(ebpm, &manifest.name) {
+            let installed = Program::load(ebpm, &manifest.name)?;
+            auto_installed = installed.auto_installed;
+
+            match manifest.version.cmp(&installed.version) {
+                Ordering::Greater => {}
+                Ordering::Equal if !force => {
+                    ebpm.report(Event::AlreadyInstalled {
+                        name: manifest.name.clone(),
+                        version: installed.version,
+                    });
+                    return Ok(Vec::new());
+                }
+                Ordering::Less if !force => {
+                    return Err(Error::Conflict(format!(
+                        "Refusing to downgrade {} from {} to {} (use --force to override)",
+                        manifest.name, installed.version, manifest.version
+                    )));
+                }
+                _ => {}
+            }
+
+            ebpm.report(Event::Upgrading {
+                name: manifest.name.clone(),
+                from: installed.version,
+                to: manifest.version.clone(),
+            });
+        }
+
+        let kept = package.install(ebpm, TransferStrategy::Move, keep)?;
+
+        let mut program = Program::load(ebpm, &manifest.name)?;
+        program.auto_installed = auto_installed;
+        program.save(ebpm)?;
+        Ok(kept)
+    }
+}
+
+/// What `ebpm inspect` shows about a package.
+#[derive(Serialize)]
+pub struct Inspection {
+    pub format: PackageFormat,
+    /// Size of the package file.
+    pub size: u64,
+    pub manifest: Manifest,
+    pub entries: Vec<ArchiveEntry>,
+    /// Entries that would be rejected on installation, or that deserve a
+    /// second look before installing.
+    pub warnings: Vec<String>,
+}
+
+fn warnings(manifest: &Manifest, entries: &[ArchiveEntry]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut names = BTreeSet::new();
+    let unchecked = manifest.checksums.is_empty();
+    if unchecked {
+        warnings.push("the package has no checksums".to_string());
+    }
+    for entry in entries {
+        let name = &entry.name;
+        let path = Path::new(name);
+        if !names.insert(name.as_str()) {
+            warnings.push(format!("'{}' is stored more than once", name));
+        }
+        if !files::is_relative(path) {
+            warnings.push(format!("'{}' is outside the package", name));
+        }
+
+        let mode = entry.mode.unwrap_or(0);
+        match (entry.kind, &entry.target) {
+            (None, _) => warnings.push(format!("'{}' has an unsupported entry type", name)),
+            (Some(FileKind::Symlink), Some(target))
+                if !files::is_contained_link(path, Path::new(target)) =>
+            {
+                warnings.push(format!(
+                    "symlink '{}' to {} points outside the package",
+                    name, target
+                ))
+            }
+            (Some(FileKind::Symlink), _) => {}
+            _ if mode & 0o6000 != 0 => warnings.push(format!("'{}' is setuid or setgid", name)),
+            _ if mode & 0o002 != 0 => warnings.push(format!("'{}' is world-writable", name)),
+            _ => {}
+        }
+
+        let checked = entry.kind != Some(FileKind::Dir) && name != "manifest.ebpm.json";
+        if checked && !unchecked && !manifest.checksums.contains_key(name) {
+            warnings.push(format!("'{}' has no checksum", name));
+        }
+    }
+
+    for name in manifest.checksums.keys() {
+        if !names.contains(name.as_str()) {
+            warnings.push(format!("'{}' has a checksum but is missing", name));
+        }
+    }
+    warnings
 }
 
 pub struct UnpackedPackage {
     manifest: Manifest,
     path: PathBuf,
+    /// Whether `path` is a scratch directory created by [`Package::unpack`].
+    temporary: bool,
+    /// The package file it was unpacked from, which is cached on installation.
+    source: Option<PathBuf>,
 }
 
 impl UnpackedPackage {
-    pub fn install(&self, strategy: TransferStrategy) -> Result {
-        std::env::set_current_dir(self.path.as_path()).unwrap();
-        
-        let resource = ProgramResources::new(&self.manifest.name);
-        let transfer = FilesTransfer::new(strategy);
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
 
-        transfer
-            .execute_transfer(&self.manifest.files, &resource.res_path)
-            .map_err(|err| format!("Failed to transfer files: {}", err))?;
-           
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Installs the package through a [`Transaction`], so a failure at any step
+    /// leaves the previously installed version untouched.
+    ///
+    /// The new version becomes the active one. The previously active version
+    /// is kept aside for [`Program::rollback`], unless `keep` is set and it
+    /// stays installed next to it.
+    ///
+    /// Returns the config files that were modified since the previous
+    /// installation and kept, each with the packaged version next to it
+    /// under [`CONFIG_SUFFIX`] to be merged by hand.
+    pub fn install(
+        &self,
+        ebpm: &Ebpm,
+        strategy: TransferStrategy,
+        keep: bool,
+    ) -> Result<Vec<String>> {
+        let name = &self.manifest.name;
+        let resources = ebpm.version_resources(name, &self.manifest.version);
+        let res_path = resources.res_path.clone();
+        let previous = match Program::is_installed(ebpm, name) {
+            true => Some((Program::load(ebpm, name)?, ebpm.resources(name))),
+            false => None,
+        };
+        let old_version = previous
+            .as_ref()
+            .map(|(program, _)| program.version.clone());
+        let (before, after) = match old_version {
+            Some(_) => (Hook::PreUpgrade, Hook::PostUpgrade),
+            None => (Hook::PreInstall, Hook::PostInstall),
+        };
+        let context = Context {
+            root: ebpm.root(),
+            res: &res_path,
+            name,
+            version: &self.manifest.version,
+            old_version: old_version.as_ref(),
+        };
+        let active = previous.as_ref().map(|_| ebpm.resources(name));
+        let replaced = previous.filter(|(_, dir)| dir.manifest != resources.manifest);
+        if let (true, Some((program, dir))) = (keep, &replaced) {
+            if dir.manifest == dir.active {
+                return Err(Error::Conflict(format!(
+                    "{} {} was installed without a versioned directory and cannot be kept, \
+                     reinstall it first",
+                    program.name, program.version
+                )));
+            }
+        }
+
+        // Sources outside of a temporary unpack directory have to survive a rollback,
+        // so they are only removed once the installation has been committed.
+        let staging_strategy = match self.temporary {
+            true => strategy,
+            false => TransferStrategy::Copy,
+        };
 
+        let files = files::expand(&self.path, &self.manifest)?;
+        let transaction = Transaction::begin(resources, active, ebpm.cancellation())?;
+        let transfer = FilesTransfer::new(staging_strategy);
+
+        transfer.execute_transfer(&self.path, &files, transaction.staging())?;
+        let manifest = self.path.join("manifest.ebpm.json");
         transfer
-            .transfer_file("manifest.ebpm.json", &resource.manifest)
-            .map_err(|err| err.to_string())?;
+            .transfer_file(&manifest, transaction.staged_manifest())
+            .with_path(&manifest)?;
+
+        let kept = self.protect_config(ebpm, &files, transaction.staging())?;
+        transaction.check_interrupted()?;
 
+        if let Some(script) = self.manifest.hooks.get(&before) {
+            context.run_staged(
+                &before.to_string(),
+                Path::new(script),
+                transaction.staging(),
+            )?;
+        }
         if self.manifest.install_script.is_empty().not() {
-            Command::new("sh")
-                .current_dir(&resource.res_path)
-                .arg(&self.manifest.install_script)
-                .spawn()
-                .unwrap()
-                .wait()
-                .unwrap();
+            let script = Path::new(&self.manifest.install_script);
+            context.run_staged("install_script", script, transaction.staging())?;
         };
-        Ok(())
+
+        let mut program = Program::load_from(transaction.staged_manifest())?;
+        program.installed = database::scan(transaction.staging())?;
+        self.mark_config(&mut program.installed, &kept);
+        program.package = match &self.source {
+            Some(source) => Some(cache::add(ebpm, source)?),
+            None => None,
+        };
+        program.save_to(transaction.staged_manifest())?;
+
+        transaction.commit(|resources| {
+            let Some(script) = self.manifest.hooks.get(&after) else {
+                return Ok(());
+            };
+            context.run(&after.to_string(), Path::new(script), &resources.res_path)?;
+
+            // Files the hook created belong to the program as well.
+            program.installed = database::scan(&resources.res_path)?;
+            self.mark_config(&mut program.installed, &kept);
+            program.save_to(&resources.manifest)
+        })?;
+
+        if let (false, Some((program, dir))) = (keep, &replaced) {
+            program.retire(dir)?;
+        }
+        if let (TransferStrategy::Move, false) = (strategy, self.temporary) {
+            FilesTransfer::remove(&self.path, &files)?;
+        }
+        Ok(kept)
+    }
+
+    /// Keeps the staged config files that the user modified since the previous
+    /// installation, putting the packaged version next to them with
+    /// [`CONFIG_SUFFIX`]. Returns the kept files.
+    fn protect_config(
+        &self,
+        ebpm: &Ebpm,
+        files: &[PackageFile],
+        staging: &Path,
+    ) -> Result<Vec<String>> {
+        let name = &self.manifest.name;
+        if self.manifest.config.is_empty() || !Program::is_installed(ebpm, name) {
+            return Ok(Vec::new());
+        }
+        let previous = Program::load(ebpm, name)?;
+        let res_path = ebpm.resources(name).res_path;
+
+        let mut kept = Vec::new();
+        for file in files.iter() {
+            if file.kind != FileKind::File || !self.manifest.is_config(&file.dst) {
+                continue;
+            }
+            let current = res_path.join(&file.dst);
+            if !fs::symlink_metadata(&current).is_ok_and(|it| it.is_file()) {
+                continue;
+            }
+
+            let hash = sha256_file(&current).with_path(&current)?;
+            let recorded = previous
+                .installed
+                .iter()
+                .find(|it| it.path == file.dst)
+                .and_then(|it| it.sha256.as_ref());
+            let staged = staging.join(&file.dst);
+            // Unrecorded files are taken as modified, as they can't be told apart.
+            if recorded == Some(&hash) || sha256_file(&staged).with_path(&staged)? == hash {
+                continue;
+            }
+
+            let new = staging.join(format!("{}{}", file.dst, CONFIG_SUFFIX));
+            fs::rename(&staged, &new).with_path(&new)?;
+            FilesTransfer::new(TransferStrategy::Copy)
+                .transfer_file(&current, &staged)
+                .with_path(&current)?;
+            kept.push(file.dst.clone());
+        }
+        Ok(kept)
     }
 
-    pub fn pack(&self) -> Package {
-        std::env::set_current_dir(self.path.as_path()).unwrap();
-        let manifest = String::from("manifest.ebpm.json");
-        let files: Vec<ArchivedFile> = self
-            .manifest
-            .files
+    /// Flags the config files among the `installed` entries. Files in `kept`
+    /// are recorded with the hash of the packaged version, so that they still
+    /// count as modified on the next upgrade.
+    fn mark_config(&self, installed: &mut [InstalledFile], kept: &[String]) {
+        let packaged: BTreeMap<String, (u64, Option<String>)> = installed
             .iter()
-            .chain(std::iter::once(&manifest))
-            .map(|file| ArchivedFile::File(file))
+            .filter_map(|file| {
+                let path = file.path.strip_suffix(CONFIG_SUFFIX)?;
+                kept.iter()
+                    .any(|it| it == path)
+                    .then(|| (path.to_string(), (file.size, file.sha256.clone())))
+            })
             .collect();
 
-        let arvhiver = Archiver::new(files);
-        let archive_name = format!("{}.ebpm.zip", self.manifest.name);
-        arvhiver.compress(&archive_name);
-        Package {
-            path: PathBuf::from(archive_name),
+        for file in installed.iter_mut() {
+            if file.kind != FileKind::File || !self.manifest.is_config(&file.path) {
+                continue;
+            }
+            file.config = true;
+            if let Some((size, sha256)) = packaged.get(&file.path) {
+                file.size = *size;
+                file.sha256 = sha256.clone();
+            }
+        }
+    }
+
+    /// Hashes every file of the expanded manifest `files`, keyed by their
+    /// path relative to the package root.
+    fn checksums(&self, files: &[PackageFile]) -> Result<BTreeMap<String, String>> {
+        files
+            .iter()
+            .filter(|file| file.kind != FileKind::Dir)
+            .map(|file| {
+                let path = self.path.join(&file.src);
+                let hash = database::sha256(&path, file.kind).with_path(&file.src)?;
+                Ok((file.archived.clone(), hash.unwrap_or_default()))
+            })
+            .collect()
+    }
+
+    /// Compares the package contents against the checksums recorded at build
+    /// time. A package with files but no checksums fails, as its checksums
+    /// may have been stripped along with a change to the files.
+    pub fn verify(&self) -> Result<()> {
+        if self.manifest.checksums.is_empty() && !self.manifest.files.is_empty() {
+            return Err(Error::integrity(
+                &self.path,
+                "package has no checksums, rebuild it with ebpm build",
+            ));
+        }
+
+        let actual = files::expand(&self.path, &self.manifest)
+            .and_then(|files| self.checksums(&files))
+            .map_err(|err| match err {
+                Error::Io { path, source } => Error::integrity(
+                    &self.path,
+                    format!("package is incomplete, {}: {}", path.display(), source),
+                ),
+                err => err,
+            })?;
+
+        let mut problems = Vec::new();
+        for (file, expected) in self.manifest.checksums.iter() {
+            match actual.get(file) {
+                None => problems.push(format!("missing file {}", file)),
+                Some(hash) if hash != expected => {
+                    problems.push(format!("checksum mismatch for {}", file))
+                }
+                Some(_) => {}
+            }
+        }
+        actual
+            .keys()
+            .filter(|file| !self.manifest.checksums.contains_key(*file))
+            .for_each(|file| problems.push(format!("unexpected file {}", file)));
+
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(Error::integrity(&self.path, problems.join(", "))),
         }
     }
+
+    /// Packs the package into `<name>.ebpm.<extension>` inside its own directory.
+    pub fn pack(&self, options: &BuildOptions) -> Result<Package> {
+        let expanded = files::expand(&self.path, &self.manifest)?;
+
+        let mut manifest = self.manifest.clone();
+        manifest.checksums = self.checksums(&expanded)?;
+        // The archive has no .ebpmignore, so its patterns travel in the manifest to
+        // expand `files` at install time exactly as they were expanded here.
+        manifest.exclude.extend(files::ignore_patterns(&self.path)?);
+        manifest.files = manifest.files.iter().map(FileEntry::archived).collect();
+        let manifest = serde_json::to_string_pretty(&manifest).unwrap();
+
+        let sources: Vec<String> = expanded
+            .iter()
+            .map(|file| self.path.join(&file.src).to_string_lossy().into_owned())
+            .collect();
+        let files: Vec<ArchivedFile> = sources
+            .iter()
+            .zip(expanded.iter())
+            .map(|(source, file)| match file.kind {
+                FileKind::Dir => ArchivedFile::Dir(source, &file.archived),
+                _ => ArchivedFile::FileWithNewName(source, &file.archived),
+            })
+            .chain(std::iter::once(ArchivedFile::Data(
+                "manifest.ebpm.json",
+                manifest.as_bytes(),
+            )))
+            .collect();
+
+        let arvhiver = Archiver::new(files);
+        let path = self.path.join(format!(
+            "{}.ebpm.{}",
+            self.manifest.name,
+            options.format.extension()
+        ));
+        arvhiver.compress(&path, options)?;
+        Ok(Package::new(path))
+    }
 }
 
 impl TryFrom<&Path> for UnpackedPackage {
     type Error = Error;
 
-    fn try_from(value: &Path) -> result::Result<Self, Self::Error> {
-        let manifest: Manifest = File::open(value.join("manifest.ebpm.json"))
-            .map_err(|err| format!("Failed to open manifest file: {}", err))?
-            .try_into()
-            .map_err(|err: serde_json::Error| format!("Failed to parse manifest file: {}", err))?;
+    fn try_from(value: &Path) -> Result<Self> {
+        let manifest = Manifest::load(&value.join("manifest.ebpm.json"))?;
 
         Ok(Self {
             path: value.into(),
             manifest,
+            temporary: false,
+            source: None,
         })
     }
 }
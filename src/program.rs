@@ -1,29 +1,179 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env::{self},
-    fs::{self, create_dir, File},
+    fs::{self, create_dir_all, File},
     io::{BufReader, Read, Write},
     os::unix::fs::PermissionsExt,
-    path::PathBuf,
-    process::{exit, Command},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
 };
 
-use serde::{Deserialize, Serialize};
+use semver::{Version, VersionReq};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use tempfile::Builder;
+
+use crate::{
+    cache, config,
+    database::{self, Difference, FileKind, InstalledFile},
+    error::{Error, Result, WithPath},
+    event::Event,
+    files::{self, FileEntry},
+    filetransfer::{FilesTransfer, TransferStrategy},
+    hook::{Context, Hook},
+    package::Package,
+    util::sha256_file,
+    Ebpm,
+};
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T> {
+    let file = File::open(path).with_path(path)?;
+    let mut reader = BufReader::new(file);
+    let mut json = String::new();
+    reader.read_to_string(&mut json).with_path(path)?;
+
+    serde_json::from_str(json.as_str()).map_err(|source| Error::Manifest {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Splits a program given as `name` or `name@version`.
+pub fn parse_spec(spec: &str) -> Result<(&str, Option<Version>)> {
+    let (name, version) = match spec.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (spec, None),
+    };
+    validate_name(name)?;
+    let version = version
+        .map(|version| {
+            Version::parse(version)
+                .map_err(|_| Error::Usage(format!("'{}' is not a valid version", version)))
+        })
+        .transpose()?;
+    Ok((name, version))
+}
+
+/// Checks that `name` is usable as a file name in the install root: ASCII
+/// letters, digits, `-`, `_`, `.` and `+`, not starting with a dot.
+pub fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|it| it.is_ascii_alphanumeric() || "-_.+".contains(it));
+    match valid {
+        true => Ok(()),
+        false => Err(Error::Usage(format!(
+            "'{}' is not a valid program name",
+            name
+        ))),
+    }
+}
+
+fn checked_name<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    validate_name(&name).map_err(de::Error::custom)?;
+    Ok(name)
+}
+
+fn checked_dependencies<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, VersionReq>, D::Error> {
+    let dependencies = BTreeMap::<String, VersionReq>::deserialize(deserializer)?;
+    for name in dependencies.keys() {
+        validate_name(name).map_err(de::Error::custom)?;
+    }
+    Ok(dependencies)
+}
+
+fn checked_hooks<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<Hook, String>, D::Error> {
+    let hooks = BTreeMap::<Hook, String>::deserialize(deserializer)?;
+    if let Some(script) = hooks.values().find(|it| !files::is_relative(Path::new(it))) {
+        return Err(de::Error::custom(format!(
+            "'{}' is not a path inside the resource directory",
+            script
+        )));
+    }
+    Ok(hooks)
+}
+
+fn checked_script<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let script = String::deserialize(deserializer)?;
+    if !script.is_empty() && !files::is_relative(Path::new(&script)) {
+        return Err(de::Error::custom(format!(
+            "'{}' is not a path inside the resource directory",
+            script
+        )));
+    }
+    Ok(script)
+}
+
+fn checked_paths<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    let paths = Vec::<String>::deserialize(deserializer)?;
+    if let Some(path) = paths.iter().find(|it| !files::is_relative(Path::new(it))) {
+        return Err(de::Error::custom(format!(
+            "'{}' is not a path inside the resource directory",
+            path
+        )));
+    }
+    Ok(paths)
+}
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Manifest {
+    #[serde(deserialize_with = "checked_name")]
     pub name: String,
-    pub files: Vec<String>,
+    pub version: Version,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default, deserialize_with = "checked_dependencies")]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Paths, glob patterns or mappings relative to the package root, see [`crate::files::expand`].
+    pub files: Vec<FileEntry>,
+    /// Patterns left out of `files`, in addition to the ones in `.ebpmignore`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Files or directories in the resource directory holding user-editable
+    /// configuration, which upgrades do not overwrite once modified.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        deserialize_with = "checked_paths"
+    )]
+    pub config: Vec<String>,
+    /// SHA-256 of every packed file, recorded by `ebpm build`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Scripts run at points of the program lifecycle, see [`Hook`].
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "checked_hooks"
+    )]
+    pub hooks: BTreeMap<Hook, String>,
+    /// Run in the staging directory after the before hook, see [`Hook`].
+    #[serde(default, deserialize_with = "checked_script")]
     pub install_script: String,
-    remove_script: String,
-    cmd: String,
+    /// Run before the files are removed, after the `pre_remove` hook.
+    #[serde(default, deserialize_with = "checked_script")]
+    pub remove_script: String,
+    pub cmd: String,
 }
 
 impl Manifest {
-    pub fn new(name: String, files: &Vec<String>) -> Self {
+    pub fn new(name: String, version: Version, files: &[String]) -> Self {
         Self {
             name,
-            files: files.clone(),
+            version,
+            description: String::new(),
+            dependencies: BTreeMap::new(),
+            files: files.iter().cloned().map(FileEntry::Path).collect(),
+            exclude: Vec::new(),
+            config: Vec::new(),
+            checksums: BTreeMap::new(),
+            hooks: BTreeMap::new(),
             cmd: String::new(),
             install_script: String::new(),
             remove_script: String::new(),
@@ -31,55 +181,154 @@ impl Manifest {
     }
 }
 
-impl TryFrom<File> for Manifest {
-    type Error = serde_json::Error;
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        read_json(path)
+    }
 
-    fn try_from(value: File) -> Result<Self, Self::Error> {
-        let mut reader = BufReader::new(value);
-        let mut json = String::new();
-        reader.read_to_string(&mut json).unwrap();
-        serde_json::from_str(json.as_str())
+    /// Whether `path`, relative to the resource directory, is a config file.
+    pub fn is_config(&self, path: &str) -> bool {
+        self.config.iter().any(|entry| {
+            let entry = entry.trim_start_matches("./").trim_end_matches('/');
+            path == entry
+                || path
+                    .strip_prefix(entry)
+                    .is_some_and(|it| it.starts_with('/'))
+        })
     }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
-pub(crate) struct Program {
-    pub(crate) name: String,
-    pub(crate) files: Vec<String>,
+pub struct Program {
+    #[serde(deserialize_with = "checked_name")]
+    pub name: String,
+    // Programs installed before versioning was introduced have no version recorded.
+    #[serde(default = "Program::unknown_version")]
+    pub version: Version,
+    #[serde(default)]
+    description: String,
+    #[serde(default, deserialize_with = "checked_dependencies")]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Set when the program was installed only to satisfy another package's dependencies.
+    #[serde(default)]
+    pub auto_installed: bool,
+    pub files: Vec<FileEntry>,
     cmd: String,
+    #[serde(default, deserialize_with = "checked_script")]
     install_script: String,
+    #[serde(default, deserialize_with = "checked_script")]
     remove_script: String,
+    #[serde(
+        default,
+        skip_serializing_if = "BTreeMap::is_empty",
+        deserialize_with = "checked_hooks"
+    )]
+    hooks: BTreeMap<Hook, String>,
+    /// Everything found in the resource directory right after installation.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub installed: Vec<InstalledFile>,
+    /// SHA-256 of the package this version was installed from, which is kept
+    /// in the package cache. Unset for programs installed from a directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
 }
 
 impl Program {
-    pub(crate) fn load(name: &str) -> Self {
-        let path = std::env::home_dir()
-            .unwrap()
-            .join("Applications")
-            .join(name.to_string() + ".json");
-
-        let file = std::fs::File::open(path).unwrap_or_else(|_e| {
-            println!("Program '{}' doesn't exist!", name);
-            exit(-1);
-        });
-        let mut reader = BufReader::new(file);
-        let mut json = String::new();
-        reader.read_to_string(&mut json).unwrap();
+    fn unknown_version() -> Version {
+        Version::new(0, 0, 0)
+    }
 
-        let program: Program = serde_json::from_str(&json.as_str()).unwrap_or_else(|e| {
-            println!("JSON manifest parsing error: {}", e);
-            exit(-1);
-        });
+    pub fn is_installed(ebpm: &Ebpm, spec: &str) -> bool {
+        parse_spec(spec).is_ok_and(|(name, version)| match version {
+            Some(version) => ebpm.version_resources(name, &version).manifest.is_file(),
+            None => ebpm.resources(name).manifest.is_file(),
+        })
+    }
+
+    /// Loads the active version of a program, or the given one for `name@version`.
+    pub fn load(ebpm: &Ebpm, spec: &str) -> Result<Self> {
+        let (name, version) = parse_spec(spec)?;
+        let path = match &version {
+            Some(version) => ebpm.version_resources(name, version).manifest,
+            None => ebpm.resources(name).manifest,
+        };
+
+        if !path.is_file() {
+            return Err(Error::NotFound(format!(
+                "Program '{}' doesn't exist!",
+                spec
+            )));
+        }
+
+        Self::load_from(&path)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        read_json(path)
+    }
+
+    /// Loads every installed version of every program, sorted by name and version.
+    pub fn installed_programs(ebpm: &Ebpm) -> Result<Vec<Self>> {
+        let records: Vec<PathBuf> = fs::read_dir(ebpm.root())
+            .map(|entries| {
+                entries
+                    .filter_map(|it| it.ok())
+                    // Links only point at the record of the active version.
+                    .filter(|it| it.file_type().is_ok_and(|it| !it.is_symlink()))
+                    .map(|it| it.path())
+                    .filter(|it| it.extension().is_some_and(|it| it == "json"))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut programs = Vec::new();
+        for record in records {
+            let spec = record.file_stem().unwrap().to_string_lossy();
+            if parse_spec(&spec).is_ok() {
+                programs.push(Self::load_from(&record)?);
+            }
+        }
+        programs.sort_by(|a, b| a.name.cmp(&b.name).then(a.version.cmp(&b.version)));
+        Ok(programs)
+    }
 
-        program
+    /// Every installed version of the program `name`.
+    pub fn versions(ebpm: &Ebpm, name: &str) -> Result<Vec<Self>> {
+        let mut programs = Self::installed_programs(ebpm)?;
+        programs.retain(|it| it.name == name);
+        Ok(programs)
     }
 
-    pub(crate) fn run(&self) {
-        let dir = ProgramResources::from(self);
+    /// The paths of this version, which are unversioned for programs
+    /// installed before versions were kept apart.
+    pub fn resources(&self, ebpm: &Ebpm) -> ProgramResources {
+        let resources = ebpm.version_resources(&self.name, &self.version);
+        match resources.manifest.is_file() {
+            true => resources,
+            false => ebpm.resources(&self.name),
+        }
+    }
+
+    /// Whether this is the version the launcher runs.
+    pub fn is_active(&self, ebpm: &Ebpm) -> bool {
+        self.resources(ebpm).manifest == ebpm.resources(&self.name).manifest
+    }
+
+    pub fn save(&self, ebpm: &Ebpm) -> Result<()> {
+        self.save_to(&self.resources(ebpm).manifest)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap()).with_path(path)
+    }
+
+    /// Runs the program command with `args` appended, returning its exit status.
+    pub fn run(&self, ebpm: &Ebpm, args: &[String]) -> Result<ExitStatus> {
+        let dir = self.resources(ebpm);
         let mut vars: HashMap<String, String> = env::vars().collect();
         vars.insert(
             "RES".to_string(),
-            dir.res_path.to_str().unwrap().to_string(),
+            dir.res_path.to_string_lossy().into_owned(),
         );
 
         let mut cmd = String::new();
@@ -87,9 +336,9 @@ impl Program {
         cmd.push_str(&self.cmd);
         cmd.push(' ');
 
-        env::args().skip(3).for_each(|i| {
-            cmd.push_str(i.replace("\\", "\\\\").replace("#", "\\#").as_str());
-            cmd.push_str(" ");
+        args.iter().for_each(|i| {
+            cmd.push_str(i.replace('\\', "\\\\").replace('#', "\\#").as_str());
+            cmd.push(' ');
         });
 
         Command::new("sh")
@@ -97,108 +346,364 @@ impl Program {
             .env_clear()
             .envs(vars)
             .arg(cmd)
-            .spawn()
-            .unwrap_or_else(|er| {
-                println!("Failed to run '{}' ", self.name);
-                println!("Error: {}", er.to_string());
-                exit(-1);
-            })
-            .wait()
-            .unwrap();
-    }
-
-    pub(crate) fn remove(&self) {
-        println!("Removing {}...", self.name);
-        let dir = ProgramResources::from(self);
-        if self.remove_script.len() > 0 {
-            if let Err(er) = Command::new("bash")
-                .current_dir(&dir.res_path)
-                .arg(&self.remove_script)
-                .spawn()
-            {
-                println!("Failed to run removing script'{}' ", self.remove_script);
-                println!("Error: {}", er.to_string());
-                exit(-1);
+            .status()
+            .with_path(&dir.res_path)
+    }
+
+    /// Removes this version, along with the launcher once no other version is left.
+    pub fn remove(&self, ebpm: &Ebpm) -> Result<()> {
+        ebpm.report(Event::Removing {
+            name: self.name.clone(),
+            version: self.version.clone(),
+        });
+        let dir = self.resources(ebpm);
+        let context = Context {
+            root: ebpm.root(),
+            res: &dir.res_path,
+            name: &self.name,
+            version: &self.version,
+            old_version: None,
+        };
+        if let Some(script) = self.hooks.get(&Hook::PreRemove) {
+            context.run(
+                &Hook::PreRemove.to_string(),
+                Path::new(script),
+                &dir.res_path,
+            )?;
+        }
+        if !self.remove_script.is_empty() {
+            let script = Path::new(&self.remove_script);
+            context.run("remove_script", script, &dir.res_path)?;
+        }
+
+        // The script is removed along with the other files, so a copy of it is run.
+        let post_remove = match self.hooks.get(&Hook::PostRemove) {
+            Some(script) => {
+                let copy = Builder::new()
+                    .prefix("ebpm-hook")
+                    .tempfile_in(ebpm.temp_dir())
+                    .with_path(ebpm.temp_dir())?
+                    .into_temp_path();
+                let script = dir.res_path.join(script);
+                fs::copy(&script, &copy).with_path(&script)?;
+                Some(copy)
+            }
+            None => None,
+        };
+
+        self.discard(ebpm, &dir)?;
+        if Self::versions(ebpm, &self.name)?.is_empty() {
+            if dir.exe_path.exists() {
+                fs::remove_file(&dir.exe_path).with_path(&dir.exe_path)?;
+            }
+            if fs::symlink_metadata(&dir.active).is_ok() {
+                fs::remove_file(&dir.active).with_path(&dir.active)?;
             }
+            remove_retired(&dir)?;
+        }
+        if let Some(script) = post_remove {
+            context.run(&Hook::PostRemove.to_string(), &script, ebpm.root())?;
+        }
+        ebpm.report(Event::Removed {
+            name: self.name.clone(),
+            version: self.version.clone(),
+        });
+        Ok(())
+    }
+
+    /// Deletes the record and the installed files of this version at `dir`.
+    pub(crate) fn discard(&self, ebpm: &Ebpm, dir: &ProgramResources) -> Result<()> {
+        // The record of an unversioned program may have been replaced by a link already.
+        if fs::symlink_metadata(&dir.manifest).is_ok_and(|it| it.is_file()) {
+            fs::remove_file(&dir.manifest).with_path(&dir.manifest)?;
         }
 
-        fs::remove_file(dir.exe_path).unwrap();
-        fs::remove_file(dir.manifest).unwrap();
-        fs::remove_dir_all(dir.res_path).unwrap();
-        println!("Removing finished");
+        // Programs installed before the file database existed are removed wholesale.
+        if self.installed.is_empty() {
+            fs::remove_dir_all(&dir.res_path).with_path(&dir.res_path)?;
+        } else if !database::remove(&dir.res_path, &self.installed)? {
+            ebpm.report(Event::Warning(format!(
+                "{} contains files not installed by ebpm and was kept",
+                dir.res_path.display()
+            )));
+        }
+        Ok(())
     }
-}
 
-pub(crate) struct ProgramResources {
-    pub(crate) res_path: PathBuf,
-    pub(crate) exe_path: PathBuf,
-    pub(crate) manifest: PathBuf,
-}
+    /// Moves this version at `dir` aside as the one [`Program::rollback`]
+    /// restores, replacing the version kept there before.
+    pub(crate) fn retire(&self, dir: &ProgramResources) -> Result<()> {
+        remove_retired(dir)?;
+        if dir.res_path.exists() {
+            fs::rename(&dir.res_path, &dir.previous_res).with_path(&dir.res_path)?;
+        }
+        // The record is written from memory, as the one of an unversioned
+        // program has been replaced by the link to the new version already.
+        self.save_to(&dir.previous_manifest)?;
+        if fs::symlink_metadata(&dir.manifest).is_ok_and(|it| it.is_file()) {
+            fs::remove_file(&dir.manifest).with_path(&dir.manifest)?;
+        }
+        Ok(())
+    }
 
-impl ProgramResources {
-    pub(crate) fn create_dir(path: &PathBuf) {
-        match std::fs::metadata(path) {
-            Err(_) => create_dir(path).unwrap(),
-            Ok(e) => {
-                e.is_file().then(|| {
-                    println!(
-                        "Error: cannot create '{}' because file with same name exist.",
-                        path.to_str().unwrap()
-                    );
-                    exit(-1)
-                });
+    /// Restores the version of `name` that was replaced last, with its files as
+    /// they were left by its install script, and makes it the active one. The
+    /// version it replaces is kept in turn, so a rollback can be undone.
+    pub fn rollback(ebpm: &Ebpm, name: &str) -> Result<Self> {
+        let current = Self::load(ebpm, name)?;
+        let current_dir = current.resources(ebpm);
+        if !current_dir.previous_manifest.is_file() {
+            return Err(Error::NotFound(format!(
+                "No previous version of {} is kept",
+                name
+            )));
+        }
+
+        let previous = Self::load_from(&current_dir.previous_manifest)?;
+        let dir = ebpm.version_resources(name, &previous.version);
+        if dir.manifest.is_file() {
+            return Err(Error::Conflict(format!(
+                "{} {} is installed already, switch to it instead",
+                name, previous.version
+            )));
+        }
+
+        let restored = (|| {
+            if dir.previous_res.exists() {
+                fs::rename(&dir.previous_res, &dir.res_path).with_path(&dir.previous_res)?;
             }
+            previous.save_to(&dir.manifest)?;
+            dir.activate()
+        })();
+        if let Err(err) = restored {
+            // Put everything back where it was, the current version is still active.
+            _ = fs::remove_file(&dir.manifest);
+            _ = fs::rename(&dir.res_path, &dir.previous_res);
+            return Err(err);
+        }
+
+        fs::remove_file(&dir.previous_manifest).with_path(&dir.previous_manifest)?;
+        current.retire(&current_dir)?;
+        Ok(previous)
+    }
+
+    /// Compares the resource directory against the files recorded at installation.
+    pub fn verify(&self, ebpm: &Ebpm) -> Result<Vec<Difference>> {
+        let res_path = self.resources(ebpm).res_path;
+        let actual = match res_path.is_dir() {
+            true => database::scan(&res_path)?,
+            false => Vec::new(),
         };
+        Ok(database::diff(&self.installed, &actual))
+    }
+
+    /// The cached package this version was installed from, checked against
+    /// the recorded hash.
+    pub fn cached_package(&self, ebpm: &Ebpm) -> Result<Package> {
+        let path = self.package.as_ref().map(|it| cache::path(ebpm, it));
+        let Some((expected, path)) = self.package.as_ref().zip(path.filter(|it| it.is_file()))
+        else {
+            return Err(Error::NotFound(format!(
+                "No cached package of {} {}, reinstall it instead",
+                self.name, self.version
+            )));
+        };
+        if sha256_file(&path).with_path(&path)? != *expected {
+            return Err(Error::integrity(
+                &path,
+                "cached package does not match the installed one",
+            ));
+        }
+        Ok(Package::new(path))
     }
 
-    pub fn new(name: &str) -> Self {
-        let mut res = env::home_dir().unwrap();
-        res.push("Applications");
-        Self::create_dir(&res);
-        let mut exe = res.clone();
+    /// Restores missing and modified entries from the cached package and
+    /// returns the differences that remain. Unexpected files are left alone,
+    /// and files written by the install script cannot be restored unless only
+    /// their mode changed.
+    pub fn repair(&self, ebpm: &Ebpm) -> Result<Vec<Difference>> {
+        let differences = self.verify(ebpm)?;
+        let damaged: Vec<&str> = differences
+            .iter()
+            .filter(|it| !matches!(it, Difference::Unexpected(_)))
+            .map(Difference::path)
+            .collect();
+        if damaged.is_empty() {
+            return Ok(differences);
+        }
 
-        res.push("res");
-        Self::create_dir(&res);
-        res.push(name);
-        Self::create_dir(&res);
+        let (package, _dir) = self.cached_package(ebpm)?.unpack(ebpm)?;
+        let sources: HashMap<String, PathBuf> = files::expand(package.path(), package.manifest())?
+            .into_iter()
+            .map(|file| (file.dst, package.path().join(file.src)))
+            .collect();
 
-        exe.push("exe");
-        Self::create_dir(&exe);
+        let res_path = self.resources(ebpm).res_path;
+        let transfer = FilesTransfer::new(TransferStrategy::Copy);
+        // Children of a damaged directory are restored along with it.
+        let files = self.installed.iter().filter(|file| {
+            damaged.iter().any(|path| {
+                file.path == *path
+                    || file
+                        .path
+                        .strip_prefix(path)
+                        .is_some_and(|it| it.starts_with('/'))
+            })
+        });
+        for file in files {
+            let path = res_path.join(&file.path);
+            let current = fs::symlink_metadata(&path).ok();
+            let kind = current.as_ref().map(FileKind::of);
 
-        exe.push(name);
+            if file.kind == FileKind::Dir {
+                if kind.is_some_and(|it| it != FileKind::Dir) {
+                    fs::remove_file(&path).with_path(&path)?;
+                }
+                fs::create_dir_all(&path)
+                    .and_then(|_| fs::set_permissions(&path, fs::Permissions::from_mode(file.mode)))
+                    .with_path(&path)?;
+                continue;
+            }
 
-        let mut _file = match std::fs::metadata(exe.clone()) {
-            Err(_) => {
-                let mut file = File::create(exe.clone()).unwrap();
-                file.write(b"#!/bin/bash\n").unwrap();
-                file.write(b"ebpm run ").unwrap();
-                file.write(name.as_bytes()).unwrap();
-                file.write(b" $@").unwrap();
-                fs::set_permissions(exe.clone(), fs::Permissions::from_mode(0o770)).unwrap();
+            let unchanged = kind == Some(file.kind)
+                && file.kind == FileKind::File
+                && file.sha256.is_some()
+                && sha256_file(&path).ok() == file.sha256;
+            if !unchanged {
+                let Some(source) = sources.get(&file.path) else {
+                    continue;
+                };
+                match kind {
+                    // A directory in place of a file may hold anything, so it is kept.
+                    Some(FileKind::Dir) => continue,
+                    Some(_) => fs::remove_file(&path).with_path(&path)?,
+                    None => {}
+                }
+                transfer.transfer_file(source, &path).with_path(&path)?;
             }
-            Ok(e) => {
-                e.is_dir().then(|| {
-                    println!(
-                        "Error: cannot create '{}' because directory with same name exist.",
-                        exe.to_str().unwrap()
-                    );
-                    exit(-1)
-                });
+            if file.kind == FileKind::File {
+                fs::set_permissions(&path, fs::Permissions::from_mode(file.mode))
+                    .with_path(&path)?;
             }
-        };
+        }
+        self.verify(ebpm)
+    }
+}
 
-        let mut manifest = env::home_dir().unwrap();
-        manifest.push("Applications");
-        manifest.push(name.to_string() + ".json");
+/// Deletes the version kept for a rollback.
+fn remove_retired(dir: &ProgramResources) -> Result<()> {
+    if dir.previous_res.exists() {
+        fs::remove_dir_all(&dir.previous_res).with_path(&dir.previous_res)?;
+    }
+    if dir.previous_manifest.exists() {
+        fs::remove_file(&dir.previous_manifest).with_path(&dir.previous_manifest)?;
+    }
+    Ok(())
+}
+
+/// The paths of one installed version of a program. Versions are kept side
+/// by side in `res/<name>@<version>` with their record in `<name>@<version>.json`,
+/// and `<name>.json` links to the record of the active version. Programs
+/// installed before that have no version in their paths.
+pub struct ProgramResources {
+    name: String,
+    version: Option<Version>,
+    root: PathBuf,
+    pub res_path: PathBuf,
+    pub exe_path: PathBuf,
+    pub manifest: PathBuf,
+    /// The record of the active version.
+    pub active: PathBuf,
+    /// Where the last replaced version is kept for `ebpm rollback`.
+    pub previous_res: PathBuf,
+    pub previous_manifest: PathBuf,
+}
+
+impl ProgramResources {
+    pub fn create_dir(path: &Path) -> Result<()> {
+        match std::fs::metadata(path) {
+            Err(_) => create_dir_all(path).with_path(path),
+            Ok(e) if e.is_file() => Err(Error::Conflict(format!(
+                "Cannot create '{}' because file with same name exist.",
+                path.display()
+            ))),
+            Ok(_) => Ok(()),
+        }
+    }
+
+    /// Resolves the paths of a version of a program below `root` and
+    /// `exe_dir` without touching the filesystem. Without a version, these are
+    /// the paths of a program installed before versions were kept apart.
+    pub fn new(root: &Path, exe_dir: &Path, name: &str, version: Option<&Version>) -> Self {
+        let versioned = match version {
+            Some(version) => format!("{}@{}", name, version),
+            None => name.to_string(),
+        };
         ProgramResources {
-            res_path: res,
-            exe_path: exe,
-            manifest,
+            name: name.to_string(),
+            version: version.cloned(),
+            root: root.to_path_buf(),
+            res_path: root.join("res").join(&versioned),
+            exe_path: exe_dir.join(name),
+            manifest: root.join(versioned + ".json"),
+            active: root.join(name.to_string() + ".json"),
+            previous_res: root.join("res").join(format!(".{}.previous", name)),
+            previous_manifest: root.join(format!(".{}.previous.json", name)),
         }
     }
 
-    #[deprecated]
-    pub(crate) fn from(program: &Program) -> Self {
-        Self::new(&program.name)
+    /// Creates the install root with its `res` directory and the `exe` directory.
+    pub(crate) fn create_dirs(&self) -> Result<()> {
+        let root = &self.root;
+        Self::create_dir(root)?;
+        Self::create_dir(&root.join("res"))?;
+        Self::create_dir(self.exe_path.parent().unwrap())
+    }
+
+    /// Makes this the active version by pointing the active record and the
+    /// launcher at it.
+    pub(crate) fn activate(&self) -> Result<()> {
+        if self.version.is_some() {
+            let link = self.root.join(format!(".{}.json.link", self.name));
+            _ = fs::remove_file(&link);
+            // The link is swapped in with a rename, so there always is an active version.
+            std::os::unix::fs::symlink(self.manifest.file_name().unwrap(), &link)
+                .and_then(|_| fs::rename(&link, &self.active))
+                .with_path(&self.active)?;
+        }
+        self.write_launcher()
+    }
+
+    /// Writes the launcher script into `exe`. Files there that are not ebpm
+    /// launchers are left alone.
+    fn write_launcher(&self) -> Result<()> {
+        let exe = &self.exe_path;
+        // The root is pinned so the launcher keeps working outside of the default layout.
+        let root = self.root.to_string_lossy().replace('\'', "'\\''");
+        let header = format!("#!/bin/bash\n{}='{}' ", config::HOME_VAR, root);
+
+        match std::fs::metadata(exe) {
+            Ok(e) if e.is_dir() => {
+                return Err(Error::Conflict(format!(
+                    "Cannot create '{}' because directory with same name exist.",
+                    exe.display()
+                )))
+            }
+            Ok(_) if !fs::read(exe).is_ok_and(|it| it.starts_with(header.as_bytes())) => {
+                return Ok(())
+            }
+            _ => {}
+        }
+
+        let program = match &self.version {
+            Some(version) => format!("{}@{}", self.name, version),
+            None => self.name.clone(),
+        };
+        let temporary = exe.with_file_name(format!(".{}.launcher", self.name));
+        File::create(&temporary)
+            .and_then(|mut file| write!(file, "{}ebpm run {} $@", header, program))
+            .and_then(|_| fs::set_permissions(&temporary, fs::Permissions::from_mode(0o770)))
+            .and_then(|_| fs::rename(&temporary, exe))
+            .with_path(exe)
     }
 }
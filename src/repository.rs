@@ -0,0 +1,262 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    archive::PackageFormat,
+    cache,
+    error::{Error, Result, WithPath},
+    event::Event,
+    package::Package,
+    program::Program,
+    util::sha256_file,
+    Ebpm,
+};
+
+const INDEX_FILE: &str = "index.json";
+const REPOSITORIES_FILE: &str = "repositories.list";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IndexEntry {
+    pub name: String,
+    pub version: Version,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Archive path relative to the repository root.
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Index {
+    pub packages: Vec<IndexEntry>,
+}
+
+/// A directory (or `file://` URL) containing `*.ebpm.*` packages and an `index.json`.
+pub struct Repository {
+    pub root: PathBuf,
+}
+
+impl Repository {
+    pub fn open(location: &str) -> Self {
+        let path = location.strip_prefix("file://").unwrap_or(location);
+        Self { root: path.into() }
+    }
+
+    fn list_path(ebpm: &Ebpm) -> PathBuf {
+        ebpm.root().join(REPOSITORIES_FILE)
+    }
+
+    pub fn configured(ebpm: &Ebpm) -> Vec<Repository> {
+        fs::read_to_string(Self::list_path(ebpm))
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|it| !it.is_empty())
+            .map(Self::open)
+            .collect()
+    }
+
+    fn save_configured(ebpm: &Ebpm, repositories: &[Repository]) -> Result<()> {
+        let content: String = repositories
+            .iter()
+            .map(|it| format!("{}\n", it.root.display()))
+            .collect();
+        let path = Self::list_path(ebpm);
+        fs::create_dir_all(ebpm.root()).with_path(ebpm.root())?;
+        fs::write(&path, content).with_path(&path)
+    }
+
+    pub fn add(ebpm: &Ebpm, location: &str) -> Result<()> {
+        let repository = Self::open(location);
+        let root = fs::canonicalize(&repository.root).with_path(&repository.root)?;
+
+        let mut repositories = Self::configured(ebpm);
+        if repositories.iter().any(|it| it.root == root) {
+            return Err(Error::Conflict(format!(
+                "Repository '{}' is already added",
+                root.display()
+            )));
+        }
+        repositories.push(Self { root });
+        Self::save_configured(ebpm, &repositories)
+    }
+
+    pub fn remove(ebpm: &Ebpm, location: &str) -> Result<()> {
+        let repository = Self::open(location);
+        let root = fs::canonicalize(&repository.root).unwrap_or(repository.root);
+
+        let mut repositories = Self::configured(ebpm);
+        let count = repositories.len();
+        repositories.retain(|it| it.root != root);
+        if repositories.len() == count {
+            return Err(Error::NotFound(format!(
+                "Repository '{}' is not configured",
+                location
+            )));
+        }
+        Self::save_configured(ebpm, &repositories)
+    }
+
+    pub fn index(&self) -> Result<Index> {
+        let path = self.root.join(INDEX_FILE);
+        let json = fs::read_to_string(&path).with_path(&path)?;
+        serde_json::from_str(&json).map_err(|source| Error::Manifest { path, source })
+    }
+
+    /// Scans the repository for packages and rewrites its `index.json`.
+    pub fn generate_index(&self) -> Result<Index> {
+        let mut archives = Vec::new();
+        Self::find_archives(&self.root, &mut archives)?;
+        archives.sort();
+
+        let mut index = Index::default();
+        for archive in archives {
+            let manifest = Package::new(archive.clone()).manifest()?;
+            let relative = archive.strip_prefix(&self.root).unwrap_or(&archive);
+
+            index.packages.push(IndexEntry {
+                name: manifest.name,
+                version: manifest.version,
+                description: manifest.description,
+                dependencies: manifest.dependencies,
+                path: relative.to_string_lossy().into_owned(),
+                sha256: sha256_file(&archive).with_path(&archive)?,
+                size: fs::metadata(&archive).with_path(&archive)?.len(),
+            });
+        }
+
+        let json = serde_json::to_string_pretty(&index).unwrap();
+        let path = self.root.join(INDEX_FILE);
+        fs::write(&path, json).with_path(&path)?;
+        Ok(index)
+    }
+
+    fn find_archives(dir: &Path, archives: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir).with_path(dir)? {
+            let entry = entry.with_path(dir)?;
+            let path = entry.path();
+            if entry.file_type().with_path(&path)?.is_dir() {
+                Self::find_archives(&path, archives)?;
+            } else if PackageFormat::is_package_name(&path) {
+                archives.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Looks up the requested packages in the configured repositories and the
+/// package cache, pulling in every dependency that is not already satisfied by
+/// an installed program. Returns the requested packages and the dependencies
+/// they pulled in.
+pub fn collect(ebpm: &Ebpm, requests: &[String]) -> Result<(Vec<Package>, Vec<Package>)> {
+    // Cached packages are found offline and by versions no repository has anymore.
+    let mut available: Vec<(PathBuf, IndexEntry)> = cache::list(ebpm)?
+        .into_iter()
+        .map(|it| (cache::dir(ebpm), it.entry))
+        .collect();
+    for repository in Repository::configured(ebpm) {
+        match repository.index() {
+            Ok(index) => index
+                .packages
+                .into_iter()
+                .for_each(|entry| available.push((repository.root.clone(), entry))),
+            Err(err) => ebpm.report(Event::Warning(err.to_string())),
+        }
+    }
+
+    let mut queue = requests
+        .iter()
+        .map(|request| parse_request(request))
+        .collect::<Result<Vec<_>, _>>()?;
+    let requested: BTreeSet<String> = queue.iter().map(|(name, _)| name.clone()).collect();
+    queue.reverse();
+
+    let mut selected: BTreeMap<String, Package> = BTreeMap::new();
+    while let Some((name, requirement)) = queue.pop() {
+        if selected.contains_key(&name) {
+            continue;
+        }
+
+        if !available.iter().any(|(_, entry)| entry.name == name) {
+            return Err(Error::NotFound(format!(
+                "Package '{}' is not found in configured repositories or the package cache",
+                name
+            )));
+        }
+
+        let (root, entry) = available
+            .iter()
+            .filter(|(_, entry)| entry.name == name && requirement.matches(&entry.version))
+            .max_by(|(_, a), (_, b)| a.version.cmp(&b.version))
+            .ok_or_else(|| {
+                Error::NotFound(format!("No package satisfies {} {}", name, requirement))
+            })?;
+
+        let path = root.join(&entry.path);
+        let hash = sha256_file(&path).with_path(&path)?;
+        if hash != entry.sha256 {
+            return Err(Error::integrity(
+                &path,
+                "checksum differs from the repository index, the index is out of date or the package is corrupted",
+            ));
+        }
+
+        for (dependency, requirement) in entry.dependencies.iter() {
+            let satisfied = Program::is_installed(ebpm, dependency)
+                && requirement.matches(&Program::load(ebpm, dependency)?.version);
+            if !satisfied {
+                queue.push((dependency.clone(), requirement.clone()));
+            }
+        }
+
+        selected.insert(name, Package::new(path));
+    }
+
+    let (mut packages, mut dependencies) = (Vec::new(), Vec::new());
+    for (name, package) in selected {
+        match requested.contains(&name) {
+            true => packages.push(package),
+            false => dependencies.push(package),
+        }
+    }
+    Ok((packages, dependencies))
+}
+
+/// Parses `name` or `name@requirement`.
+fn parse_request(request: &str) -> Result<(String, VersionReq)> {
+    match request.split_once('@') {
+        Some((name, requirement)) => {
+            let requirement = VersionReq::parse(requirement).map_err(|err| {
+                Error::Usage(format!(
+                    "Invalid version requirement '{}': {}",
+                    requirement, err
+                ))
+            })?;
+            Ok((name.to_string(), requirement))
+        }
+        None => Ok((request.to_string(), VersionReq::STAR)),
+    }
+}
+
+pub fn search(ebpm: &Ebpm, term: &str) -> Vec<IndexEntry> {
+    let term = term.to_lowercase();
+    Repository::configured(ebpm)
+        .iter()
+        .filter_map(|repository| repository.index().ok())
+        .flat_map(|index| index.packages)
+        .filter(|entry| {
+            entry.name.to_lowercase().contains(&term)
+                || entry.description.to_lowercase().contains(&term)
+        })
+        .collect()
+}
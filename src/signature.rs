@@ -0,0 +1,232 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result, WithPath},
+    event::Event,
+    util::sha256_file,
+    Ebpm,
+};
+
+const KEYS_DIR: &str = "trusted-keys";
+const POLICY_FILE: &str = "policy";
+
+/// Contents of the detached `<package>.sig` file written next to a package.
+#[derive(Serialize, Deserialize)]
+struct DetachedSignature {
+    /// Hex encoded public key of the signer.
+    key: String,
+    /// Hex encoded ed25519 signature of the package SHA-256 digest.
+    signature: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Policy {
+    /// Unsigned packages are installed with a warning.
+    Warn,
+    /// Unsigned packages are refused.
+    Require,
+}
+
+impl Policy {
+    pub fn load(ebpm: &Ebpm) -> Self {
+        match fs::read_to_string(keys_dir(ebpm).join(POLICY_FILE)) {
+            Ok(policy) if policy.trim() == "require" => Policy::Require,
+            _ => Policy::Warn,
+        }
+    }
+
+    pub fn save(self, ebpm: &Ebpm) -> Result<()> {
+        let policy = match self {
+            Policy::Warn => "warn",
+            Policy::Require => "require",
+        };
+        let dir = keys_dir(ebpm);
+        fs::create_dir_all(&dir).with_path(&dir)?;
+        let path = dir.join(POLICY_FILE);
+        fs::write(&path, policy).with_path(&path)
+    }
+}
+
+impl TryFrom<&str> for Policy {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "warn" => Ok(Policy::Warn),
+            "require" => Ok(Policy::Require),
+            other => Err(Error::Usage(format!(
+                "'{}' is not a valid policy, expected 'warn' or 'require'",
+                other
+            ))),
+        }
+    }
+}
+
+fn keys_dir(ebpm: &Ebpm) -> PathBuf {
+    ebpm.root().join(KEYS_DIR)
+}
+
+pub fn signature_path(archive: &Path) -> PathBuf {
+    let mut path = archive.as_os_str().to_owned();
+    path.push(".sig");
+    path.into()
+}
+
+fn digest(archive: &Path) -> Result<Vec<u8>> {
+    let hash = sha256_file(archive).with_path(archive)?;
+    Ok(hex::decode(hash).unwrap())
+}
+
+fn parse_hex<const N: usize>(hex_key: &str, path: &Path) -> Result<[u8; N]> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::integrity(path, "malformed ed25519 data"))
+}
+
+fn verifying_key(path: &Path) -> Result<VerifyingKey> {
+    let content = fs::read_to_string(path).with_path(path)?;
+    VerifyingKey::from_bytes(&parse_hex(&content, path)?).map_err(|err| Error::integrity(path, err))
+}
+
+/// Writes a new key pair as `<name>.key` (secret) and `<name>.pub` (public),
+/// returning both paths in that order.
+pub fn generate(name: &str) -> Result<(String, String)> {
+    let key = SigningKey::generate(&mut OsRng);
+    let secret = format!("{}.key", name);
+    let public = format!("{}.pub", name);
+
+    if let Some(path) = [&secret, &public]
+        .into_iter()
+        .find(|it| Path::new(it).exists())
+    {
+        return Err(Error::Conflict(format!("{} already exists", path)));
+    }
+
+    // Created readable by the owner only, so the secret key is never exposed.
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&secret)
+        .and_then(|mut file| file.write_all(hex::encode(key.to_bytes()).as_bytes()))
+        .with_path(&secret)?;
+    fs::write(&public, hex::encode(key.verifying_key().to_bytes())).with_path(&public)?;
+
+    Ok((secret, public))
+}
+
+/// Signs `archive` with the secret key stored in `key_file`, writing `<archive>.sig`.
+pub fn sign(archive: &Path, key_file: &Path) -> Result<()> {
+    let content = fs::read_to_string(key_file).with_path(key_file)?;
+    let key = SigningKey::from_bytes(&parse_hex(&content, key_file)?);
+
+    let signature = DetachedSignature {
+        key: hex::encode(key.verifying_key().to_bytes()),
+        signature: hex::encode(key.sign(&digest(archive)?).to_bytes()),
+    };
+
+    let path = signature_path(archive);
+    fs::write(&path, serde_json::to_string_pretty(&signature).unwrap()).with_path(&path)
+}
+
+/// Checks the detached signature of `archive` against the trusted keys,
+/// applying the configured policy to unsigned packages.
+pub fn verify(ebpm: &Ebpm, archive: &Path) -> Result<()> {
+    let path = signature_path(archive);
+    let Ok(json) = fs::read_to_string(&path) else {
+        return match Policy::load(ebpm) {
+            Policy::Require => Err(Error::integrity(
+                archive,
+                "package is not signed and the policy requires signatures",
+            )),
+            Policy::Warn => {
+                ebpm.report(Event::Warning(format!(
+                    "{} is not signed",
+                    archive.display()
+                )));
+                Ok(())
+            }
+        };
+    };
+
+    let detached: DetachedSignature =
+        serde_json::from_str(&json).map_err(|source| Error::Manifest {
+            path: path.clone(),
+            source,
+        })?;
+    let signature = Signature::from_bytes(&parse_hex(&detached.signature, &path)?);
+
+    let (name, key) = list(ebpm)?
+        .into_iter()
+        .find(|(_, key)| hex::encode(key.to_bytes()) == detached.key)
+        .ok_or_else(|| {
+            Error::integrity(
+                archive,
+                format!("package is signed by an untrusted key {}", detached.key),
+            )
+        })?;
+
+    key.verify(&digest(archive)?, &signature)
+        .map_err(|_| Error::integrity(archive, "signature is invalid"))?;
+    ebpm.report(Event::SignatureVerified {
+        archive: archive.to_path_buf(),
+        key: name,
+    });
+    Ok(())
+}
+
+/// Key names become file names in the keys directory, so they must not
+/// be empty, name a path or start with a dot.
+fn validate_key_name(name: &str) -> Result<()> {
+    match name.is_empty() || name.contains('/') || name.starts_with('.') {
+        true => Err(Error::Usage(format!("'{}' is not a valid key name", name))),
+        false => Ok(()),
+    }
+}
+
+/// Adds the public key stored in `key_file` to the trusted keys under `name`.
+pub fn add(ebpm: &Ebpm, name: &str, key_file: &Path) -> Result<()> {
+    validate_key_name(name)?;
+
+    let key = verifying_key(key_file)?;
+    let dir = keys_dir(ebpm);
+    let path = dir.join(format!("{}.pub", name));
+    if path.exists() {
+        return Err(Error::Conflict(format!("Key '{}' already exists", name)));
+    }
+
+    fs::create_dir_all(&dir).with_path(&dir)?;
+    fs::write(&path, hex::encode(key.to_bytes())).with_path(&path)
+}
+
+pub fn remove(ebpm: &Ebpm, name: &str) -> Result<()> {
+    validate_key_name(name)?;
+    fs::remove_file(keys_dir(ebpm).join(format!("{}.pub", name)))
+        .map_err(|_| Error::NotFound(format!("Key '{}' doesn't exist", name)))
+}
+
+pub fn list(ebpm: &Ebpm) -> Result<Vec<(String, VerifyingKey)>> {
+    let Ok(entries) = fs::read_dir(keys_dir(ebpm)) else {
+        return Ok(Vec::new());
+    };
+
+    let mut keys = Vec::new();
+    for entry in entries.filter_map(|it| it.ok()) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(name) = file_name.strip_suffix(".pub") {
+            keys.push((name.to_string(), verifying_key(&entry.path())?));
+        }
+    }
+    keys.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(keys)
+}
@@ -0,0 +1,217 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+use filetime::FileTime;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use tar::{Archive, Builder, EntryType, Header};
+use xz2::{read::XzDecoder, write::XzEncoder};
+
+use crate::{
+    archive::{ArchiveEntry, BuildOptions, Content, Entry, Extraction, Format, ReadSeek},
+    database::FileKind,
+    error::{Error, Result, WithPath},
+};
+
+#[derive(Clone, Copy)]
+pub enum Encoding {
+    Gzip,
+    Xz,
+    Zstd,
+}
+
+/// Compressed tar archives.
+pub struct Tar(pub Encoding);
+
+/// Writes `entries` as a tar stream into `writer` and hands the writer back,
+/// so that the compression can be finished.
+fn write_entries<W: Write>(archive: &Path, writer: W, entries: &[Entry]) -> Result<W> {
+    let mut builder = Builder::new(writer);
+
+    for entry in entries {
+        let mut header = Header::new_gnu();
+        header.set_mode(entry.mode);
+        header.set_mtime(entry.mtime.max(0) as u64);
+        header.set_uid(entry.uid);
+        header.set_gid(entry.gid);
+        header.set_size(0);
+
+        let name = &entry.name;
+        match &entry.content {
+            Content::File(path) => {
+                let file = File::open(path).with_path(path)?;
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(file.metadata().with_path(path)?.len());
+                builder
+                    .append_data(&mut header, name, file)
+                    .with_path(path)?;
+            }
+            Content::Dir => {
+                header.set_entry_type(EntryType::Directory);
+                builder
+                    .append_data(&mut header, name, io::empty())
+                    .with_path(archive)?;
+            }
+            Content::Symlink(target) => {
+                header.set_entry_type(EntryType::Symlink);
+                builder
+                    .append_link(&mut header, name, target)
+                    .with_path(archive)?;
+            }
+            Content::Data(data) => {
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(data.len() as u64);
+                builder
+                    .append_data(&mut header, name, *data)
+                    .with_path(archive)?;
+            }
+        }
+    }
+    builder.into_inner().with_path(archive)
+}
+
+/// Entry names as written by other tools may start with `./`.
+fn normalize(name: &str) -> &str {
+    name.trim_start_matches("./")
+}
+
+impl Tar {
+    fn open<'a>(
+        &self,
+        archive: &Path,
+        source: &'a mut dyn ReadSeek,
+    ) -> Result<Archive<Box<dyn Read + 'a>>> {
+        let file = BufReader::new(source);
+        let reader: Box<dyn Read> = match self.0 {
+            Encoding::Gzip => Box::new(GzDecoder::new(file)),
+            Encoding::Xz => Box::new(XzDecoder::new(file)),
+            Encoding::Zstd => Box::new(zstd::Decoder::with_buffer(file).with_path(archive)?),
+        };
+        Ok(Archive::new(reader))
+    }
+}
+
+impl Format for Tar {
+    fn compress(&self, archive: &Path, entries: &[Entry], build: &BuildOptions) -> Result<()> {
+        let file = File::create(archive).with_path(archive)?;
+        let finished = match self.0 {
+            Encoding::Gzip => {
+                let level = build.level.map_or(flate2::Compression::default(), |it| {
+                    flate2::Compression::new(it as u32)
+                });
+                write_entries(archive, GzEncoder::new(file, level), entries)?.finish()
+            }
+            Encoding::Xz => {
+                let level = build.level.unwrap_or(6) as u32;
+                write_entries(archive, XzEncoder::new(file, level), entries)?.finish()
+            }
+            Encoding::Zstd => {
+                // Level 0 selects the default level of zstd.
+                let level = build.level.unwrap_or(0) as i32;
+                let encoder = zstd::Encoder::new(file, level).with_path(archive)?;
+                write_entries(archive, encoder, entries)?.finish()
+            }
+        };
+        finished.with_path(archive).map(|_| ())
+    }
+
+    fn read_file(&self, archive: &Path, source: &mut dyn ReadSeek, name: &str) -> Result<String> {
+        let archive_error = |err| Error::archive(archive, err);
+
+        let mut tar = self.open(archive, source)?;
+        for entry in tar.entries().map_err(archive_error)? {
+            let mut entry = entry.map_err(archive_error)?;
+            let path = entry.path().map_err(archive_error)?;
+            if normalize(&path.to_string_lossy()) != name {
+                continue;
+            }
+
+            let mut content = String::new();
+            entry
+                .read_to_string(&mut content)
+                .map_err(|err| Error::archive(archive, format!("{}: {}", name, err)))?;
+            return Ok(content);
+        }
+        Err(Error::archive(archive, format!("'{}' is missing", name)))
+    }
+
+    fn extract(&self, archive: &Path, source: &mut dyn ReadSeek, destination: &Path) -> Result<()> {
+        let mut tar = self.open(archive, source)?;
+        let mut extraction = Extraction::new(archive, destination)?;
+        for entry in tar.entries().map_err(|err| extraction.error(err))? {
+            let mut entry = entry.map_err(|err| extraction.error(err))?;
+            let path = entry.path().map_err(|err| extraction.error(err))?;
+            let name = normalize(&path.to_string_lossy()).to_string();
+            let header = entry.header();
+            let mode = header.mode().ok();
+            let mtime = header
+                .mtime()
+                .ok()
+                .map(|mtime| FileTime::from_unix_time(mtime as i64, 0));
+
+            match header.entry_type() {
+                EntryType::Directory => extraction.dir(&name, mode, mtime)?,
+                EntryType::Symlink => {
+                    let target = entry
+                        .link_name()
+                        .map_err(|err| extraction.error(err))?
+                        .ok_or_else(|| extraction.error(format!("'{}' has no target", name)))?;
+                    extraction.symlink(&name, &target.to_string_lossy(), mtime)?;
+                }
+                EntryType::Regular | EntryType::Continuous => {
+                    extraction.file(&name, &mut entry, mode, mtime)?
+                }
+                EntryType::XGlobalHeader => {}
+                other => {
+                    return Err(extraction.error(format!(
+                        "'{}' has the unsupported entry type {:?}",
+                        name, other
+                    )))
+                }
+            }
+        }
+        extraction.finish()
+    }
+
+    fn list(&self, archive: &Path, source: &mut dyn ReadSeek) -> Result<Vec<ArchiveEntry>> {
+        let archive_error = |err| Error::archive(archive, err);
+
+        let mut tar = self.open(archive, source)?;
+        let mut entries = Vec::new();
+        for entry in tar.entries().map_err(archive_error)? {
+            let entry = entry.map_err(archive_error)?;
+            let path = entry.path().map_err(archive_error)?;
+            let header = entry.header();
+            let kind = match header.entry_type() {
+                EntryType::Directory => Some(FileKind::Dir),
+                EntryType::Symlink => Some(FileKind::Symlink),
+                EntryType::Regular | EntryType::Continuous => Some(FileKind::File),
+                EntryType::XGlobalHeader => continue,
+                _ => None,
+            };
+
+            let name = normalize(&path.to_string_lossy())
+                .trim_end_matches('/')
+                .to_string();
+            // The root itself, as in tarballs made with `tar -C dir .`.
+            if name.is_empty() {
+                continue;
+            }
+
+            entries.push(ArchiveEntry {
+                name,
+                kind,
+                mode: header.mode().ok().map(|mode| mode & 0o7777),
+                size: header.size().map_err(archive_error)?,
+                compressed_size: None,
+                target: entry
+                    .link_name()
+                    .map_err(archive_error)?
+                    .map(|it| it.to_string_lossy().into_owned()),
+            });
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    error::{Error, Result, WithPath},
+    program::ProgramResources,
+};
+
+/// Stages an installation next to its final location and moves it into place
+/// with renames once everything succeeded. Dropping an uncommitted transaction
+/// rolls it back, restoring any previously installed version.
+pub(crate) struct Transaction {
+    resources: ProgramResources,
+    /// The version that was active before, which is activated again on a rollback.
+    previous: Option<ProgramResources>,
+    had_launcher: bool,
+    staging: PathBuf,
+    staged_manifest: PathBuf,
+    backup: PathBuf,
+    backup_manifest: PathBuf,
+    /// The record of an unversioned program, which the link to the new version replaces.
+    backup_active: PathBuf,
+    /// Checked between steps, so that an interrupted installation rolls back.
+    cancelled: Arc<AtomicBool>,
+    committed: bool,
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+fn remove_path(path: &Path) {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => _ = fs::remove_dir_all(path),
+        Ok(_) => _ = fs::remove_file(path),
+        Err(_) => {}
+    }
+}
+
+impl Transaction {
+    pub(crate) fn begin(
+        resources: ProgramResources,
+        previous: Option<ProgramResources>,
+        cancelled: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        resources.create_dirs()?;
+
+        let transaction = Self {
+            staging: sibling(&resources.res_path, "staging"),
+            staged_manifest: sibling(&resources.manifest, "staging"),
+            backup: sibling(&resources.res_path, "backup"),
+            backup_manifest: sibling(&resources.manifest, "backup"),
+            backup_active: sibling(&resources.active, "backup"),
+            had_launcher: resources.exe_path.exists(),
+            resources,
+            previous,
+            cancelled,
+            committed: false,
+        };
+
+        transaction.recover();
+        remove_path(&transaction.staging);
+        remove_path(&transaction.staged_manifest);
+        fs::create_dir(&transaction.staging).with_path(&transaction.staging)?;
+        Ok(transaction)
+    }
+
+    /// Restores a backup left behind by a commit that was killed halfway.
+    fn recover(&self) {
+        if self.backup.exists() && !self.resources.res_path.exists() {
+            _ = fs::rename(&self.backup, &self.resources.res_path);
+        }
+        if self.backup_manifest.exists() && !self.resources.manifest.exists() {
+            _ = fs::rename(&self.backup_manifest, &self.resources.manifest);
+        }
+        if self.backup_active.exists() && fs::symlink_metadata(&self.resources.active).is_err() {
+            _ = fs::rename(&self.backup_active, &self.resources.active);
+        }
+        remove_path(&self.backup);
+        remove_path(&self.backup_manifest);
+        remove_path(&self.backup_active);
+    }
+
+    pub(crate) fn staging(&self) -> &Path {
+        &self.staging
+    }
+
+    pub(crate) fn staged_manifest(&self) -> &Path {
+        &self.staged_manifest
+    }
+
+    pub(crate) fn check_interrupted(&self) -> Result<()> {
+        match self.cancelled.load(Ordering::SeqCst) {
+            true => Err(Error::Interrupted),
+            false => Ok(()),
+        }
+    }
+
+    /// Moves the staged version into place and makes it the active one. `post`
+    /// runs once it is in place, and a failure there rolls everything back.
+    pub(crate) fn commit(
+        mut self,
+        post: impl FnOnce(&ProgramResources) -> Result<()>,
+    ) -> Result<()> {
+        self.check_interrupted()?;
+
+        let res = &self.resources.res_path;
+        let manifest = &self.resources.manifest;
+        let active = &self.resources.active;
+        let had_res = res.exists();
+        let had_manifest = manifest.exists();
+
+        let result = (|| {
+            if had_res {
+                fs::rename(res, &self.backup).with_path(res)?;
+            }
+            fs::rename(&self.staging, res).with_path(res)?;
+            if had_manifest {
+                fs::rename(manifest, &self.backup_manifest).with_path(manifest)?;
+            }
+            fs::rename(&self.staged_manifest, manifest).with_path(manifest)?;
+            if manifest != active && fs::symlink_metadata(active).is_ok_and(|it| it.is_file()) {
+                fs::rename(active, &self.backup_active).with_path(active)?;
+            }
+            self.resources.activate()?;
+            post(&self.resources)
+        })();
+
+        if let Err(err) = result {
+            self.undo();
+            return Err(err);
+        }
+
+        self.committed = true;
+        remove_path(&self.backup);
+        remove_path(&self.backup_manifest);
+        remove_path(&self.backup_active);
+        Ok(())
+    }
+
+    /// Reverts a commit that failed halfway, leaving the new version staged
+    /// for the cleanup on drop.
+    fn undo(&self) {
+        let resources = &self.resources;
+        // The new tree may already be in place; move it aside so the backup can return.
+        if !self.staging.exists() && resources.res_path.exists() {
+            _ = fs::rename(&resources.res_path, &self.staging);
+        }
+        if !self.staged_manifest.exists() && resources.manifest.exists() {
+            _ = fs::rename(&resources.manifest, &self.staged_manifest);
+        }
+
+        if fs::symlink_metadata(&resources.active).is_ok_and(|it| it.is_symlink()) {
+            _ = fs::remove_file(&resources.active);
+        }
+        self.recover();
+        match &self.previous {
+            Some(previous) => _ = previous.activate(),
+            None if !self.had_launcher => _ = fs::remove_file(&resources.exe_path),
+            None => {}
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.committed {
+            remove_path(&self.staging);
+            remove_path(&self.staged_manifest);
+            self.recover();
+        }
+    }
+}
@@ -1,9 +1,14 @@
-use std::{fs, io::Write, path::PathBuf};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
+use sha2::{Digest, Sha256};
 
-pub(crate) fn request_yes_or_no(request: &str) -> bool {
+pub fn request_yes_or_no(request: &str) -> bool {
     loop {
-        print!("{} {} ", request, "[y/n]:");
+        print!("{} [y/n]: ", request);
         std::io::stdout().flush().unwrap();
 
         let string = input_string();
@@ -22,7 +27,7 @@ pub(crate) fn request_yes_or_no(request: &str) -> bool {
 }
 
 #[inline]
-pub(crate) fn input_string() -> String {
+pub fn input_string() -> String {
     let mut string = String::new();
 
     std::io::stdin().read_line(&mut string).unwrap();
@@ -55,4 +60,10 @@ impl GetSize for PathBuf {
     
         size
     }
-}
\ No newline at end of file
+}
+
+pub(crate) fn sha256_file(path: impl AsRef<Path>) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
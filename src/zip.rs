@@ -1,70 +1,172 @@
-use std::{fs::File, io, path::{Path, PathBuf}};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
 
-use zip::write::SimpleFileOptions;
+use filetime::FileTime;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use zip::{
+    extra_fields::ExtraField, result::ZipError, write::FullFileOptions, CompressionMethod,
+    DateTime, ZipArchive, ZipWriter,
+};
 
+use crate::{
+    archive::{
+        ArchiveEntry, BuildOptions, Compression, Content, Entry, Extraction, Format, ReadSeek,
+    },
+    database::FileKind,
+    error::{Error, Result, WithPath},
+};
 
-pub enum ArchivedFile<'a> {
-    File(&'a String),
-    FileWithNewName(&'a String, &'a String),
+/// Header id of the extended timestamp extra field, which keeps the exact
+/// modification time that DOS timestamps round to two seconds.
+const EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+/// Zip archives, with Bzip2 compressed entries by default.
+pub struct Zip;
+
+/// Options carrying the mode and modification time of `entry`.
+fn options(entry: &Entry, build: &BuildOptions) -> FullFileOptions<'static> {
+    let method = match build.compression.unwrap_or(Compression::Bzip2) {
+        Compression::Store => CompressionMethod::Stored,
+        Compression::Deflate => CompressionMethod::Deflated,
+        Compression::Bzip2 => CompressionMethod::Bzip2,
+        Compression::Zstd => CompressionMethod::Zstd,
+    };
+    // Without an explicit time the writer stamps entries with the current one.
+    let date = OffsetDateTime::from_unix_timestamp(entry.mtime)
+        .ok()
+        .and_then(|it| DateTime::try_from(PrimitiveDateTime::new(it.date(), it.time())).ok())
+        .unwrap_or_default();
+    let mut options = FullFileOptions::default()
+        .compression_method(method)
+        .compression_level(build.level)
+        .unix_permissions(entry.mode)
+        .last_modified_time(date);
+
+    if let Ok(mtime) = u32::try_from(entry.mtime) {
+        let mut field = vec![1];
+        field.extend(mtime.to_le_bytes());
+        // The field is a handful of bytes, so it always fits.
+        _ = options.add_extra_data(EXTENDED_TIMESTAMP, field.into_boxed_slice(), false);
+    }
+    options
 }
 
-pub struct Archiver<'a> {
-    files: Vec<ArchivedFile<'a>>,
+fn open<'a>(
+    archive: &Path,
+    source: &'a mut dyn ReadSeek,
+) -> Result<ZipArchive<&'a mut dyn ReadSeek>> {
+    ZipArchive::new(source).map_err(|err| Error::archive(archive, err))
 }
 
-impl<'a> Archiver<'a> {
-    pub fn new(files: Vec<ArchivedFile<'a>>) -> Self {
-        Self { files }
-    }
+impl Format for Zip {
+    fn compress(&self, archive: &Path, entries: &[Entry], build: &BuildOptions) -> Result<()> {
+        let zip_error = |err: ZipError| Error::archive(archive, err);
 
-    pub fn compress(&self, archive_name: &str) {
-        let mut archive = zip::ZipWriter::new(File::create(archive_name).unwrap());
-        for file in self.files.iter() {
-            
-            let (path, name) = match file {
-                ArchivedFile::File(path) => (Path::new(path), path.as_str()),
-                ArchivedFile::FileWithNewName(path, name) => (Path::new(path), name.as_str())
-            };
-            
-            let mut file = File::open(path).unwrap();
-            let options = SimpleFileOptions::default()
-                .compression_method(zip::CompressionMethod::Bzip2).unix_permissions(0o755);
-            archive.start_file(name, options).unwrap();
-            io::copy(&mut file, &mut archive).unwrap();
+        let file = File::create(archive).with_path(archive)?;
+        let mut writer = ZipWriter::new(file);
+
+        for entry in entries {
+            let options = options(entry, build);
+            match &entry.content {
+                Content::File(path) => {
+                    let mut file = File::open(path).with_path(path)?;
+                    writer.start_file(&entry.name, options).map_err(zip_error)?;
+                    io::copy(&mut file, &mut writer).with_path(path)?;
+                }
+                Content::Dir => writer
+                    .add_directory(&entry.name, options)
+                    .map_err(zip_error)?,
+                Content::Symlink(target) => writer
+                    .add_symlink(&entry.name, target.to_string_lossy(), options)
+                    .map_err(zip_error)?,
+                Content::Data(data) => {
+                    writer.start_file(&entry.name, options).map_err(zip_error)?;
+                    writer.write_all(data).with_path(archive)?;
+                }
+            }
         }
+        writer.finish().map_err(zip_error)?;
+        Ok(())
     }
-}
 
+    fn read_file(&self, archive: &Path, source: &mut dyn ReadSeek, name: &str) -> Result<String> {
+        let mut zip = open(archive, source)?;
+        let mut file = zip.by_name(name).map_err(|err| match err {
+            ZipError::FileNotFound => Error::archive(archive, format!("'{}' is missing", name)),
+            err => Error::archive(archive, err),
+        })?;
 
-pub struct Extractor {
-    archive: PathBuf,
-}
-
-impl Extractor {
-    pub fn new(archive: PathBuf) -> Self {
-        Self { archive }
+        let mut content = String::new();
+        file.read_to_string(&mut content)
+            .map_err(|err| Error::archive(archive, format!("{}: {}", name, err)))?;
+        Ok(content)
     }
 
-    pub fn extract(&self, destination: PathBuf) {
-        let mut archive = zip::ZipArchive::new(File::open(&self.archive).unwrap()).unwrap();
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).unwrap();
-            let outpath = destination.join(file.mangled_name());
-            if (&*file.name()).ends_with('/') {
-                std::fs::create_dir_all(&outpath).unwrap();
+    fn extract(&self, archive: &Path, source: &mut dyn ReadSeek, destination: &Path) -> Result<()> {
+        let mut zip = open(archive, source)?;
+        let mut extraction = Extraction::new(archive, destination)?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(|err| extraction.error(err))?;
+            let name = file.name().to_string();
+            let mode = file.unix_mode();
+            let mtime = file
+                .extra_data_fields()
+                .find_map(|field| match field {
+                    ExtraField::ExtendedTimestamp(time) => time.mod_time(),
+                    _ => None,
+                })
+                .map(i64::from)
+                .or_else(|| {
+                    let date = file.last_modified()?;
+                    Some(OffsetDateTime::try_from(date).ok()?.unix_timestamp())
+                })
+                .map(|mtime| FileTime::from_unix_time(mtime, 0));
+
+            if file.is_dir() {
+                extraction.dir(&name, mode, mtime)?;
+            } else if file.is_symlink() {
+                let mut target = String::new();
+                file.read_to_string(&mut target)
+                    .map_err(|err| extraction.error(format!("{}: {}", name, err)))?;
+                extraction.symlink(&name, &target, mtime)?;
             } else {
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(&p).unwrap();
-                    }
-                }
-                std::fs::File::create(&outpath).unwrap();
-            }
-            
-            {
-                let mut outfile = std::fs::File::create(&outpath).unwrap();
-                std::io::copy(&mut file, &mut outfile).unwrap();
+                extraction.file(&name, &mut file, mode, mtime)?;
             }
         }
+        extraction.finish()
     }
-}
\ No newline at end of file
+
+    fn list(&self, archive: &Path, source: &mut dyn ReadSeek) -> Result<Vec<ArchiveEntry>> {
+        let mut zip = open(archive, source)?;
+        let mut entries = Vec::new();
+        for i in 0..zip.len() {
+            let mut file = zip
+                .by_index(i)
+                .map_err(|err| Error::archive(archive, err))?;
+            let name = file.name().trim_end_matches('/').to_string();
+            let (kind, target) = if file.is_dir() {
+                (FileKind::Dir, None)
+            } else if file.is_symlink() {
+                let mut target = String::new();
+                file.read_to_string(&mut target)
+                    .map_err(|err| Error::archive(archive, format!("{}: {}", name, err)))?;
+                (FileKind::Symlink, Some(target))
+            } else {
+                (FileKind::File, None)
+            };
+
+            entries.push(ArchiveEntry {
+                name,
+                kind: Some(kind),
+                mode: file.unix_mode().map(|mode| mode & 0o7777),
+                size: file.size(),
+                compressed_size: Some(file.compressed_size()),
+                target,
+            });
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use std::{fs::File, io::Write, path::PathBuf};
+
+use common::Fixture;
+use ebpm::{error::Error, package::Package};
+use flate2::{write::GzEncoder, Compression};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tar::{EntryType, Header};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+const SCRIPT: &[u8] = b"echo evil\n";
+
+fn manifest() -> String {
+    json!({
+        "name": "evil",
+        "version": "1.0.0",
+        "files": ["run.sh"],
+        "cmd": "run.sh",
+        "checksums": { "run.sh": hex::encode(Sha256::digest(SCRIPT)) },
+    })
+    .to_string()
+}
+
+/// Writes a zip package by hand, as `ebpm build` never writes such entries.
+/// Each entry is a file, or a symlink when its target is given.
+fn handmade(fixture: &Fixture, entries: &[(&str, Option<&str>)]) -> Package {
+    let path = fixture.dir.path().join("evil.ebpm.zip");
+    let mut writer = ZipWriter::new(File::create(&path).unwrap());
+    let options = SimpleFileOptions::default();
+
+    writer.start_file("manifest.ebpm.json", options).unwrap();
+    writer.write_all(manifest().as_bytes()).unwrap();
+    writer.start_file("run.sh", options).unwrap();
+    writer.write_all(SCRIPT).unwrap();
+    for (name, target) in entries {
+        match target {
+            Some(target) => writer.add_symlink(*name, *target, options).unwrap(),
+            None if name.ends_with('/') => writer.add_directory(*name, options).unwrap(),
+            None => {
+                writer.start_file(*name, options).unwrap();
+                writer.write_all(b"evil\n").unwrap();
+            }
+        }
+    }
+    writer.finish().unwrap();
+    Package::new(path)
+}
+
+/// Like [`handmade`], but writes a tar.gz package.
+fn handmade_tar(fixture: &Fixture, entries: &[(&str, Option<&str>)]) -> Package {
+    let path = fixture.dir.path().join("evil.ebpm.tar.gz");
+    let encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mut append = |name: &str, kind: EntryType, target: Option<&str>, data: &[u8]| {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_mode(0o755);
+        header.set_size(data.len() as u64);
+        match target {
+            Some(target) => builder.append_link(&mut header, name, target).unwrap(),
+            None => builder.append_data(&mut header, name, data).unwrap(),
+        }
+    };
+
+    append(
+        "manifest.ebpm.json",
+        EntryType::Regular,
+        None,
+        manifest().as_bytes(),
+    );
+    append("run.sh", EntryType::Regular, None, SCRIPT);
+    for (name, target) in entries {
+        match target {
+            Some(target) => append(name, EntryType::Symlink, Some(target), b""),
+            None if name.ends_with('/') => append(name, EntryType::Directory, None, b""),
+            None => append(name, EntryType::Regular, None, b"evil\n"),
+        }
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+    Package::new(path)
+}
+
+fn install(fixture: &Fixture, package: Package) -> Error {
+    let err = fixture
+        .ebpm
+        .install(&[package], &[], false, false)
+        .unwrap_err();
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+    err
+}
+
+#[test]
+fn path_traversal_is_refused() {
+    let fixture = Fixture::new();
+    let package = handmade(&fixture, &[("../../ebpm-escaped", None)]);
+
+    let err = install(&fixture, package);
+    assert!(matches!(err, Error::Archive { .. }), "{}", err);
+    // Packages are unpacked to a directory inside the temporary directory.
+    let escaped = fixture.ebpm.temp_dir().join("../ebpm-escaped");
+    assert!(!escaped.exists());
+}
+
+#[test]
+fn absolute_path_is_refused() {
+    let fixture = Fixture::new();
+    let target: PathBuf = fixture.dir.path().join("escaped");
+    let package = handmade(&fixture, &[(target.to_str().unwrap(), None)]);
+
+    let err = install(&fixture, package);
+    assert!(matches!(err, Error::Archive { .. }), "{}", err);
+    assert!(!target.exists());
+}
+
+#[test]
+fn symlink_outside_the_package_is_refused() {
+    for target in ["/etc/passwd", "../../etc/passwd", "lib/../../passwd"] {
+        let fixture = Fixture::new();
+        let package = handmade(&fixture, &[("passwd", Some(target))]);
+
+        let err = install(&fixture, package);
+        assert!(matches!(err, Error::Archive { .. }), "{}: {}", target, err);
+    }
+}
+
+#[test]
+fn write_through_chained_symlinks_is_refused() {
+    // Each link stays inside the package by its name, but `a/l/m` is really
+    // created at `m`, from where its target leads outside.
+    let entries = [
+        ("a/", None),
+        ("a/l", Some("..")),
+        ("a/l/m", Some("../../ebpm-pwned")),
+        ("m", None),
+    ];
+    for build in [handmade, handmade_tar] {
+        let fixture = Fixture::new();
+        let package = build(&fixture, &entries);
+
+        let err = install(&fixture, package);
+        assert!(matches!(err, Error::Archive { .. }), "{}", err);
+        let escaped = fixture.ebpm.temp_dir().join("../ebpm-pwned");
+        assert!(!escaped.exists());
+    }
+}
+
+#[test]
+fn symlinks_inside_the_package_are_extracted() {
+    let entries = [
+        ("link", Some("run.sh")),
+        ("sub/", None),
+        ("sub/up", Some("../run.sh")),
+        ("sub/dir", Some("..")),
+    ];
+    for build in [handmade, handmade_tar] {
+        let fixture = Fixture::new();
+        let package = build(&fixture, &entries);
+        fixture.ebpm.install(&[package], &[], false, false).unwrap();
+        assert_eq!(fixture.read("evil", "run.sh"), "echo evil\n");
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+use common::Fixture;
+use ebpm::archive::{BuildOptions, PackageFormat};
+use filetime::FileTime;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+const FILES: &[(&str, &str)] = &[
+    ("run.sh", "echo hello\n"),
+    ("lib/a.txt", "a\n"),
+    ("lib/b.txt", "b\n"),
+];
+
+fn sha256(path: &Path) -> String {
+    hex::encode(Sha256::digest(fs::read(path).unwrap()))
+}
+
+#[test]
+fn reproducible_builds_are_identical() {
+    for format in [
+        PackageFormat::Zip,
+        PackageFormat::TarGz,
+        PackageFormat::TarXz,
+        PackageFormat::TarZst,
+    ] {
+        let fixture = Fixture::new();
+        let dir = fixture.source(
+            "hello",
+            "1.0.0",
+            FILES,
+            json!({ "files": ["run.sh", "lib"] }),
+        );
+        let options = BuildOptions {
+            format,
+            reproducible: Some(BuildOptions::source_date_epoch().unwrap()),
+            ..BuildOptions::default()
+        };
+        let first = sha256(&fixture.ebpm.build(&dir, &options).unwrap().path);
+
+        // Neither timestamps nor permissions of the sources end up in the package.
+        let mtime = FileTime::from_unix_time(1_700_000_000, 0);
+        for (path, _) in FILES {
+            filetime::set_file_mtime(dir.join(path), mtime).unwrap();
+        }
+        filetime::set_file_mtime(dir.join("lib"), mtime).unwrap();
+        fs::set_permissions(dir.join("lib/a.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+        let second = sha256(&fixture.ebpm.build(&dir, &options).unwrap().path);
+        assert_eq!(first, second);
+    }
+}
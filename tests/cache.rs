@@ -0,0 +1,90 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use ebpm::{cache, repository};
+use serde_json::json;
+
+/// Installs `app` 1.0.0 and upgrades it through `versions`.
+fn upgraded(fixture: &Fixture, versions: &[&str]) {
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "1.0.0\n")], json!({})));
+    for version in versions {
+        let run = format!("{}\n", version);
+        let package = fixture.package("app", version, &[("run.sh", &run)], json!({}));
+        fixture.ebpm.upgrade(&package, false, false).unwrap();
+    }
+}
+
+fn cached(fixture: &Fixture) -> Vec<(String, bool)> {
+    cache::list(&fixture.ebpm)
+        .unwrap()
+        .into_iter()
+        .map(|it| (it.entry.version.to_string(), it.in_use))
+        .collect()
+}
+
+fn versions(removed: Vec<cache::CachedPackage>) -> Vec<String> {
+    removed
+        .into_iter()
+        .map(|it| it.entry.version.to_string())
+        .collect()
+}
+
+#[test]
+fn installed_packages_are_cached() {
+    let fixture = Fixture::new();
+    upgraded(&fixture, &[]);
+
+    let program = fixture.ebpm.program("app").unwrap();
+    let sha256 = program.package.unwrap();
+    assert!(cache::path(&fixture.ebpm, &sha256).is_file());
+    assert_eq!(cached(&fixture), [("1.0.0".to_string(), true)]);
+}
+
+#[test]
+fn prune_and_clean_keep_packages_in_use() {
+    let fixture = Fixture::new();
+    upgraded(&fixture, &["1.1.0", "1.2.0", "1.3.0"]);
+    // The active version and the one kept for rollback are in use.
+    let expected: Vec<(String, bool)> = [
+        ("1.0.0", false),
+        ("1.1.0", false),
+        ("1.2.0", true),
+        ("1.3.0", true),
+    ]
+    .iter()
+    .map(|(version, used)| (version.to_string(), *used))
+    .collect();
+    assert_eq!(cached(&fixture), expected);
+
+    let removed = cache::prune(&fixture.ebpm, 3).unwrap();
+    assert_eq!(versions(removed), ["1.0.0"]);
+    let removed = cache::clean(&fixture.ebpm).unwrap();
+    assert_eq!(versions(removed), ["1.1.0"]);
+    assert_eq!(cached(&fixture).len(), 2);
+
+    fixture.ebpm.remove("app").unwrap();
+    let removed = cache::clean(&fixture.ebpm).unwrap();
+    assert_eq!(versions(removed), ["1.3.0", "1.2.0"]);
+    assert!(fs::read_dir(cache::dir(&fixture.ebpm))
+        .unwrap()
+        .next()
+        .is_none());
+}
+
+#[test]
+fn removed_programs_are_reinstalled_from_the_cache() {
+    let fixture = Fixture::new();
+    upgraded(&fixture, &[]);
+    fixture.ebpm.remove("app").unwrap();
+    fs::remove_dir_all(fixture.dir.path().join("src")).unwrap();
+
+    let names = ["app".to_string()];
+    let (packages, dependencies) = repository::collect(&fixture.ebpm, &names).unwrap();
+    fixture
+        .ebpm
+        .install(&packages, &dependencies, false, false)
+        .unwrap();
+    assert_eq!(fixture.read("app", "run.sh"), "1.0.0\n");
+}
@@ -0,0 +1,80 @@
+#![allow(dead_code)]
+
+use std::{fs, path::PathBuf};
+
+use ebpm::{archive::BuildOptions, package::Package, Ebpm};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+/// An install root inside a temporary directory, which also holds the
+/// package sources.
+pub struct Fixture {
+    pub dir: TempDir,
+    pub ebpm: Ebpm,
+}
+
+impl Fixture {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let ebpm = Ebpm::new(dir.path().join("root"));
+        Self { dir, ebpm }
+    }
+
+    /// Writes a package directory for `name` `version` with the given files,
+    /// and `extra` merged into its manifest. `files` lists every file unless
+    /// `extra` sets it.
+    pub fn source(
+        &self,
+        name: &str,
+        version: &str,
+        files: &[(&str, &str)],
+        extra: Value,
+    ) -> PathBuf {
+        let dir = self.dir.path().join(format!("src/{}-{}", name, version));
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, content).unwrap();
+        }
+
+        let mut manifest = json!({
+            "name": name,
+            "version": version,
+            "files": files.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+            "cmd": "run.sh",
+        });
+        manifest
+            .as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        fs::write(dir.join("manifest.ebpm.json"), manifest.to_string()).unwrap();
+        dir
+    }
+
+    /// Builds a zip package from a directory written by [`Fixture::source`].
+    pub fn package(
+        &self,
+        name: &str,
+        version: &str,
+        files: &[(&str, &str)],
+        extra: Value,
+    ) -> Package {
+        let dir = self.source(name, version, files, extra);
+        self.ebpm.build(&dir, &BuildOptions::default()).unwrap()
+    }
+
+    pub fn install(&self, package: &Package) {
+        self.ebpm
+            .install(std::slice::from_ref(package), &[], false, false)
+            .unwrap();
+    }
+
+    /// Resource directory of the active version of `name`.
+    pub fn res(&self, name: &str) -> PathBuf {
+        self.ebpm.resources(name).res_path
+    }
+
+    pub fn read(&self, name: &str, path: &str) -> String {
+        fs::read_to_string(self.res(name).join(path)).unwrap()
+    }
+}
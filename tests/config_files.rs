@@ -0,0 +1,52 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use ebpm::package::CONFIG_SUFFIX;
+use serde_json::json;
+
+#[test]
+fn modified_config_is_kept() {
+    let fixture = Fixture::new();
+    let config = json!({ "config": ["app.conf", "default.conf"] });
+    let files = [
+        ("run.sh", "v1\n"),
+        ("app.conf", "v1\n"),
+        ("default.conf", "v1\n"),
+    ];
+    fixture.install(&fixture.package("app", "1.0.0", &files, config.clone()));
+    fs::write(fixture.res("app").join("app.conf"), "edited\n").unwrap();
+
+    let files = [
+        ("run.sh", "v2\n"),
+        ("app.conf", "v2\n"),
+        ("default.conf", "v2\n"),
+    ];
+    let package = fixture.package("app", "2.0.0", &files, config);
+    let kept = fixture.ebpm.upgrade(&package, false, false).unwrap();
+    assert_eq!(kept, ["app.conf"]);
+
+    assert_eq!(fixture.read("app", "run.sh"), "v2\n");
+    assert_eq!(fixture.read("app", "app.conf"), "edited\n");
+    let new = format!("app.conf{}", CONFIG_SUFFIX);
+    assert_eq!(fixture.read("app", &new), "v2\n");
+    // Unmodified config files are upgraded like any other file.
+    assert_eq!(fixture.read("app", "default.conf"), "v2\n");
+    assert!(!fixture.res("app").join("default.conf.ebpmnew").exists());
+}
+
+#[test]
+fn edited_config_is_not_reported_by_verify() {
+    let fixture = Fixture::new();
+    let files = [("run.sh", "v1\n"), ("app.conf", "v1\n")];
+    fixture.install(&fixture.package("app", "1.0.0", &files, json!({ "config": ["app.conf"] })));
+    fs::write(fixture.res("app").join("app.conf"), "edited\n").unwrap();
+    assert!(fixture.ebpm.verify("app").unwrap().is_empty());
+
+    // Reinstalling the same version keeps the edit as well.
+    let package = fixture.package("app", "1.0.0", &files, json!({ "config": ["app.conf"] }));
+    let kept = fixture.ebpm.upgrade(&package, true, false).unwrap();
+    assert_eq!(kept, ["app.conf"]);
+    assert_eq!(fixture.read("app", "app.conf"), "edited\n");
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::Fixture;
+use ebpm::{error::Error, event::Event, package::Package};
+use serde_json::{json, Value};
+
+fn package(fixture: &Fixture, name: &str, version: &str, dependencies: Value) -> Package {
+    let files = [("run.sh", "echo hello\n")];
+    fixture.package(
+        name,
+        version,
+        &files,
+        json!({ "dependencies": dependencies }),
+    )
+}
+
+fn conflict(fixture: &Fixture, packages: &[Package]) {
+    let err = fixture
+        .ebpm
+        .install(packages, &[], false, false)
+        .unwrap_err();
+    assert!(matches!(err, Error::Conflict(_)), "{}", err);
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+}
+
+#[test]
+fn dependencies_are_installed_first() {
+    let mut fixture = Fixture::new();
+    let installed = Arc::new(Mutex::new(Vec::new()));
+    let reported = installed.clone();
+    fixture.ebpm.set_reporter(move |event| {
+        if let Event::Installing { name, .. } = event {
+            reported.lock().unwrap().push(name.clone());
+        }
+    });
+
+    let packages = [
+        package(&fixture, "app", "1.0.0", json!({ "lib": "^1.2" })),
+        package(&fixture, "lib", "1.3.0", json!({ "base": "1" })),
+        package(&fixture, "base", "1.0.0", json!({})),
+    ];
+    fixture.ebpm.install(&packages, &[], false, false).unwrap();
+    assert_eq!(*installed.lock().unwrap(), ["base", "lib", "app"]);
+}
+
+#[test]
+fn pulled_in_dependencies_are_marked() {
+    let fixture = Fixture::new();
+    let app = package(&fixture, "app", "1.0.0", json!({ "lib": "1" }));
+    let lib = package(&fixture, "lib", "1.0.0", json!({}));
+    fixture.ebpm.install(&[app], &[lib], false, false).unwrap();
+
+    assert!(!fixture.ebpm.program("app").unwrap().auto_installed);
+    assert!(fixture.ebpm.program("lib").unwrap().auto_installed);
+
+    // Installing it explicitly later keeps it for good.
+    let lib = package(&fixture, "lib", "1.1.0", json!({}));
+    fixture.install(&lib);
+    assert!(!fixture.ebpm.program("lib").unwrap().auto_installed);
+}
+
+#[test]
+fn unsatisfied_dependencies_are_refused() {
+    let fixture = Fixture::new();
+    let packages = [
+        package(&fixture, "app", "1.0.0", json!({ "lib": "^2" })),
+        package(&fixture, "lib", "1.0.0", json!({})),
+    ];
+    conflict(&fixture, &packages);
+    conflict(&fixture, &packages[..1]);
+
+    // Forced, they are only reported.
+    fixture.ebpm.install(&packages, &[], true, false).unwrap();
+    assert_eq!(fixture.ebpm.list().unwrap().len(), 2);
+}
+
+#[test]
+fn conflicting_packages_are_refused() {
+    let fixture = Fixture::new();
+    let first = package(&fixture, "lib", "1.0.0", json!({}));
+    let second = package(&fixture, "lib", "2.0.0", json!({}));
+    conflict(&fixture, &[first, second]);
+
+    let cycle = [
+        package(&fixture, "a", "1.0.0", json!({ "b": "1" })),
+        package(&fixture, "b", "1.0.0", json!({ "a": "1" })),
+    ];
+    conflict(&fixture, &cycle);
+}
+
+#[test]
+fn installed_dependents_stay_satisfied() {
+    let fixture = Fixture::new();
+    let packages = [
+        package(&fixture, "app", "1.0.0", json!({ "lib": "1" })),
+        package(&fixture, "lib", "1.0.0", json!({})),
+    ];
+    fixture.ebpm.install(&packages, &[], false, false).unwrap();
+
+    let lib = package(&fixture, "lib", "2.0.0", json!({}));
+    let err = fixture.ebpm.upgrade(&lib, false, false).unwrap_err();
+    assert!(matches!(err, Error::Conflict(_)), "{}", err);
+    assert_eq!(
+        fixture.ebpm.program("lib").unwrap().version.to_string(),
+        "1.0.0"
+    );
+}
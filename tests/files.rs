@@ -0,0 +1,112 @@
+mod common;
+
+use common::Fixture;
+use ebpm::{
+    database::FileKind,
+    filetransfer::TransferStrategy,
+    package::{Package, UnpackedPackage},
+};
+use serde_json::{json, Value};
+
+/// Files recorded as installed for `name`, sorted.
+fn installed(fixture: &Fixture, name: &str) -> Vec<String> {
+    let program = fixture.ebpm.program(name).unwrap();
+    let mut files: Vec<String> = program
+        .installed
+        .into_iter()
+        .filter(|it| it.kind == FileKind::File)
+        .map(|it| it.path)
+        .collect();
+    files.sort();
+    files
+}
+
+/// Files stored in the package besides its manifest, sorted.
+fn archived(package: &Package) -> Vec<String> {
+    let mut names: Vec<String> = package
+        .inspect()
+        .unwrap()
+        .entries
+        .into_iter()
+        .filter(|it| it.kind == Some(FileKind::File) && it.name != "manifest.ebpm.json")
+        .map(|it| it.name)
+        .collect();
+    names.sort();
+    names
+}
+
+fn build_and_install(fixture: &Fixture, files: &[(&str, &str)], extra: Value) -> Vec<String> {
+    let package = fixture.package("app", "1.0.0", files, extra);
+    let archived = archived(&package);
+    fixture.install(&package);
+    assert_eq!(archived, installed(fixture, "app"));
+    archived
+}
+
+#[test]
+fn globs_keep_their_paths() {
+    let fixture = Fixture::new();
+    let files = [
+        ("run.sh", "run\n"),
+        ("assets/a.png", "a\n"),
+        ("assets/icons/b.png", "b\n"),
+        ("assets/c.txt", "c\n"),
+    ];
+    let extra = json!({ "files": ["run.sh", "assets/**/*.png"] });
+    assert_eq!(
+        build_and_install(&fixture, &files, extra),
+        ["assets/a.png", "assets/icons/b.png", "run.sh"]
+    );
+}
+
+#[test]
+fn excluded_and_ignored_files_are_left_out() {
+    let fixture = Fixture::new();
+    let files = [
+        ("run.sh", "run\n"),
+        (".ebpmignore", "# build output\n*.tmp\n\n"),
+        ("lib/a.txt", "a\n"),
+        ("lib/a.o", "object\n"),
+        ("lib/sub/b.tmp", "temporary\n"),
+        ("lib/sub/c.txt", "c\n"),
+        ("notes.tmp", "listed\n"),
+    ];
+    // Explicitly listed files are packed even when they are ignored.
+    let extra = json!({ "files": ["run.sh", "lib", "notes.tmp"], "exclude": ["lib/*.o"] });
+    assert_eq!(
+        build_and_install(&fixture, &files, extra),
+        ["lib/a.txt", "lib/sub/c.txt", "notes.tmp", "run.sh"]
+    );
+}
+
+#[test]
+fn mapped_files_land_at_their_destination() {
+    let fixture = Fixture::new();
+    let files = [("run.sh", "run\n"), ("target/release/tool", "tool\n")];
+    let extra = json!({
+        "files": ["run.sh", { "src": "target/release/tool", "dst": "bin/tool" }],
+    });
+    assert_eq!(
+        build_and_install(&fixture, &files, extra),
+        ["bin/tool", "run.sh"]
+    );
+    assert_eq!(fixture.read("app", "bin/tool"), "tool\n");
+}
+
+#[test]
+fn mapped_files_are_installed_from_a_directory() {
+    let fixture = Fixture::new();
+    let files = [("run.sh", "run\n"), ("target/release/tool", "tool\n")];
+    let extra = json!({
+        "files": ["run.sh", { "src": "target/release/tool", "dst": "bin/tool" }],
+    });
+    let dir = fixture.source("app", "1.0.0", &files, extra);
+    let package = UnpackedPackage::try_from(dir.as_path()).unwrap();
+    fixture
+        .ebpm
+        .install_unpacked(&package, TransferStrategy::Copy, false)
+        .unwrap();
+
+    assert_eq!(installed(&fixture, "app"), ["bin/tool", "run.sh"]);
+    assert!(dir.join("target/release/tool").is_file());
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use common::Fixture;
+use ebpm::{
+    archive::{BuildOptions, PackageFormat},
+    package::Package,
+};
+use serde_json::json;
+
+const FILES: &[(&str, &str)] = &[("run.sh", "echo hello\n"), ("lib/a.txt", "a\n")];
+
+#[test]
+fn every_format_installs_the_same_files() {
+    for format in [
+        PackageFormat::Zip,
+        PackageFormat::TarGz,
+        PackageFormat::TarXz,
+        PackageFormat::TarZst,
+    ] {
+        let fixture = Fixture::new();
+        let dir = fixture.source(
+            "hello",
+            "1.0.0",
+            FILES,
+            json!({ "files": ["run.sh", "lib"] }),
+        );
+        fs::set_permissions(dir.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        let options = BuildOptions {
+            format,
+            ..BuildOptions::default()
+        };
+        let built = fixture.ebpm.build(&dir, &options).unwrap();
+        assert!(built
+            .path
+            .to_str()
+            .unwrap()
+            .ends_with(&format!(".ebpm.{}", format)));
+
+        // The format is told by the contents, not by the file name.
+        let renamed = fixture.dir.path().join("package");
+        fs::rename(&built.path, &renamed).unwrap();
+        let package = Package::new(renamed);
+        assert!(package.inspect().unwrap().format == format, "{}", format);
+
+        fixture.install(&package);
+        assert_eq!(fixture.read("hello", "run.sh"), "echo hello\n");
+        assert_eq!(fixture.read("hello", "lib/a.txt"), "a\n");
+        let mode = fs::metadata(fixture.res("hello").join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755, "{}", format);
+    }
+}
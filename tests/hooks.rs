@@ -0,0 +1,88 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use ebpm::error::Error;
+use serde_json::{json, Value};
+
+/// Logs every hook it is run for to `hooks.log` in the install root.
+const LOG: &str = r#"echo "$EBPM_HOOK $EBPM_VERSION $EBPM_OLD_VERSION" >> "$EBPM_HOME/hooks.log"
+if [ "$EBPM_HOOK" = post_install ]; then echo created > "$RES/created"; fi
+"#;
+
+fn hooks() -> Value {
+    let hooks: serde_json::Map<String, Value> = [
+        "pre_install",
+        "post_install",
+        "pre_upgrade",
+        "post_upgrade",
+        "pre_remove",
+        "post_remove",
+    ]
+    .iter()
+    .map(|hook| (hook.to_string(), json!("log.sh")))
+    .collect();
+    json!({ "hooks": hooks })
+}
+
+fn version(fixture: &Fixture, name: &str) -> String {
+    fixture.ebpm.program(name).unwrap().version.to_string()
+}
+
+#[test]
+fn hooks_run_with_the_program_in_their_environment() {
+    let fixture = Fixture::new();
+    let files = [("run.sh", "v1\n"), ("log.sh", LOG)];
+    fixture.install(&fixture.package("app", "1.0.0", &files, hooks()));
+    // Files written by a post hook belong to the program.
+    assert_eq!(fixture.read("app", "created"), "created\n");
+    assert!(fixture.ebpm.verify("app").unwrap().is_empty());
+
+    let package = fixture.package("app", "2.0.0", &files, hooks());
+    fixture.ebpm.upgrade(&package, false, false).unwrap();
+    fixture.ebpm.remove("app").unwrap();
+
+    let log = fs::read_to_string(fixture.ebpm.root().join("hooks.log")).unwrap();
+    assert_eq!(
+        log.lines().collect::<Vec<_>>(),
+        [
+            "pre_install 1.0.0 ",
+            "post_install 1.0.0 ",
+            "pre_upgrade 2.0.0 1.0.0",
+            "post_upgrade 2.0.0 1.0.0",
+            "pre_remove 2.0.0 ",
+            "post_remove 2.0.0 ",
+        ]
+    );
+}
+
+#[test]
+fn failing_pre_hook_installs_nothing() {
+    let fixture = Fixture::new();
+    let files = [("run.sh", "v1\n"), ("fail.sh", "exit 1\n")];
+    let hooks = json!({ "hooks": { "pre_install": "fail.sh" } });
+    let package = fixture.package("app", "1.0.0", &files, hooks);
+    let err = fixture
+        .ebpm
+        .install(&[package], &[], false, false)
+        .unwrap_err();
+    assert!(matches!(err, Error::Script { .. }), "{}", err);
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+}
+
+#[test]
+fn failing_post_hook_rolls_back() {
+    let fixture = Fixture::new();
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+
+    let files = [("run.sh", "v2\n"), ("fail.sh", "exit 1\n")];
+    let hooks = json!({ "hooks": { "post_upgrade": "fail.sh" } });
+    let package = fixture.package("app", "2.0.0", &files, hooks);
+    let err = fixture.ebpm.upgrade(&package, false, false).unwrap_err();
+    assert!(matches!(err, Error::Script { .. }), "{}", err);
+
+    assert_eq!(version(&fixture, "app"), "1.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v1\n");
+    assert_eq!(fixture.ebpm.list().unwrap().len(), 1);
+}
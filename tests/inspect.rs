@@ -0,0 +1,99 @@
+mod common;
+
+use std::{fs::File, process::Command};
+
+use common::Fixture;
+use ebpm::package::Package;
+use flate2::{write::GzEncoder, Compression};
+use serde_json::{json, Value};
+use tar::{EntryType, Header};
+
+#[test]
+fn built_package_has_no_warnings() {
+    let fixture = Fixture::new();
+    let files = [("run.sh", "echo hello\n"), ("lib/a.txt", "a\n")];
+    let extra = json!({ "files": ["run.sh", "lib"], "description": "Says hello" });
+    let package = fixture.package("hello", "1.0.0", &files, extra);
+
+    let inspection = package.inspect().unwrap();
+    assert!(inspection.warnings.is_empty(), "{:?}", inspection.warnings);
+    assert_eq!(inspection.manifest.description, "Says hello");
+    let names: Vec<&str> = inspection
+        .entries
+        .iter()
+        .map(|it| it.name.as_str())
+        .collect();
+    assert_eq!(names, ["run.sh", "lib", "lib/a.txt", "manifest.ebpm.json"]);
+    // Inspecting leaves nothing behind to install.
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+}
+
+#[test]
+fn suspicious_entries_are_pointed_out() {
+    let fixture = Fixture::new();
+    // Written as tar, as zip cannot store setuid bits.
+    let path = fixture.dir.path().join("odd.ebpm.tar.gz");
+    let encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let manifest = json!({
+        "name": "odd",
+        "version": "1.0.0",
+        "files": ["run.sh"],
+        "cmd": "run.sh",
+        "checksums": { "run.sh": "0", "gone.txt": "0" },
+    })
+    .to_string();
+    let entries = [
+        ("manifest.ebpm.json", 0o644, manifest.as_bytes()),
+        ("run.sh", 0o755, b"odd\n"),
+        ("setuid", 0o4755, b"odd\n"),
+        ("open.txt", 0o666, b"odd\n"),
+    ];
+    for (name, mode, data) in entries {
+        let mut header = Header::new_gnu();
+        header.set_mode(mode);
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, name, data).unwrap();
+    }
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Symlink);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "passwd", "../../etc/passwd")
+        .unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let inspection = Package::new(path).inspect().unwrap();
+    assert_eq!(
+        inspection.warnings,
+        [
+            "'setuid' is setuid or setgid",
+            "'setuid' has no checksum",
+            "'open.txt' is world-writable",
+            "'open.txt' has no checksum",
+            "symlink 'passwd' to ../../etc/passwd points outside the package",
+            "'passwd' has no checksum",
+            "'gone.txt' has a checksum but is missing",
+        ]
+    );
+}
+
+#[test]
+fn json_output_describes_the_package() {
+    let fixture = Fixture::new();
+    let package = fixture.package("hello", "1.0.0", &[("run.sh", "echo hello\n")], json!({}));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ebpm"))
+        .args(["inspect", "--json"])
+        .arg(&package.path)
+        .env("HOME", fixture.dir.path())
+        .env("EBPM_HOME", fixture.ebpm.root())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let json: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["format"], "zip");
+    assert_eq!(json["manifest"]["name"], "hello");
+    assert_eq!(json["entries"][0]["name"], "run.sh");
+    assert_eq!(json["warnings"], json!([]));
+}
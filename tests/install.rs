@@ -0,0 +1,59 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::Fixture;
+use serde_json::json;
+
+const FILES: &[(&str, &str)] = &[("run.sh", "echo hello\n"), ("data/greeting.txt", "hello\n")];
+
+#[test]
+fn build_and_install_round_trip() {
+    let fixture = Fixture::new();
+    let package = fixture.package("hello", "1.0.0", FILES, json!({}));
+    fixture.install(&package);
+
+    let programs = fixture.ebpm.list().unwrap();
+    assert_eq!(programs.len(), 1);
+    assert_eq!(programs[0].name, "hello");
+    assert_eq!(programs[0].version.to_string(), "1.0.0");
+    assert_eq!(fixture.read("hello", "run.sh"), "echo hello\n");
+    // Listed paths are installed under their file name.
+    assert_eq!(fixture.read("hello", "greeting.txt"), "hello\n");
+    assert!(fixture.ebpm.verify("hello").unwrap().is_empty());
+    assert!(fixture.ebpm.resources("hello").exe_path.is_file());
+
+    let res = fixture.res("hello");
+    fixture.ebpm.remove("hello").unwrap();
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+    assert!(!res.exists());
+}
+
+#[test]
+fn progress_is_passed_to_the_reporter() {
+    let mut fixture = Fixture::new();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let reported = events.clone();
+    fixture
+        .ebpm
+        .set_reporter(move |event| reported.lock().unwrap().push(event.to_string()));
+
+    fixture.install(&fixture.package("hello", "1.0.0", FILES, json!({})));
+    fixture.ebpm.remove("hello").unwrap();
+    let events = events.lock().unwrap();
+    // Packages are unsigned unless the policy requires signatures.
+    assert!(
+        events[0].ends_with("hello.ebpm.zip is not signed"),
+        "{}",
+        events[0]
+    );
+    assert_eq!(
+        events[1..],
+        [
+            "installing hello 1.0.0",
+            "Installing finished",
+            "Removing hello 1.0.0...",
+            "Removing finished",
+        ]
+    );
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::Path,
+};
+
+use common::Fixture;
+use ebpm::error::Error;
+use serde_json::{json, Value};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+const FILES: &[(&str, &str)] = &[("run.sh", "echo hello\n"), ("data/greeting.txt", "hello\n")];
+
+/// Rewrites the zip at `path`, passing the name and content of every file
+/// through `change`.
+fn rewrite(path: &Path, change: impl Fn(&str, Vec<u8>) -> Vec<u8>) {
+    let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+    let rewritten = path.with_extension("rewritten");
+    let mut writer = ZipWriter::new(File::create(&rewritten).unwrap());
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).unwrap();
+        if entry.is_dir() {
+            writer
+                .add_directory(entry.name(), SimpleFileOptions::default())
+                .unwrap();
+            continue;
+        }
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).unwrap();
+        writer
+            .start_file(entry.name(), SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&change(entry.name(), data)).unwrap();
+    }
+    writer.finish().unwrap();
+    fs::rename(rewritten, path).unwrap();
+}
+
+fn refused(fixture: &Fixture, package: ebpm::package::Package) {
+    let err = fixture
+        .ebpm
+        .install(&[package], &[], false, false)
+        .unwrap_err();
+    assert!(matches!(err, Error::Integrity { .. }), "{}", err);
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+}
+
+#[test]
+fn built_packages_record_checksums() {
+    let fixture = Fixture::new();
+    let package = fixture.package("hello", "1.0.0", FILES, json!({}));
+    let manifest = package.manifest().unwrap();
+    let names: Vec<&str> = manifest.checksums.keys().map(String::as_str).collect();
+    assert_eq!(names, ["data/greeting.txt", "run.sh"]);
+}
+
+#[test]
+fn tampered_package_is_refused() {
+    let fixture = Fixture::new();
+    let package = fixture.package("hello", "1.0.0", FILES, json!({}));
+    rewrite(&package.path, |name, data| match name {
+        "data/greeting.txt" => b"goodbye\n".to_vec(),
+        _ => data,
+    });
+    refused(&fixture, package);
+}
+
+#[test]
+fn package_without_checksums_is_refused() {
+    let fixture = Fixture::new();
+    let package = fixture.package("hello", "1.0.0", FILES, json!({}));
+    rewrite(&package.path, |name, data| match name {
+        "manifest.ebpm.json" => {
+            let mut manifest: Value = serde_json::from_slice(&data).unwrap();
+            manifest.as_object_mut().unwrap().remove("checksums");
+            manifest.to_string().into_bytes()
+        }
+        "data/greeting.txt" => b"goodbye\n".to_vec(),
+        _ => data,
+    });
+    refused(&fixture, package);
+}
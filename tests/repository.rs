@@ -0,0 +1,138 @@
+mod common;
+
+use std::{fs, path::PathBuf};
+
+use common::Fixture;
+use ebpm::{
+    error::Error,
+    repository::{self, Repository},
+};
+use serde_json::{json, Value};
+
+/// Builds the packages into a repository directory and indexes it.
+fn repository(fixture: &Fixture, packages: &[(&str, &str, Value)]) -> PathBuf {
+    let dir = fixture.dir.path().join("repo");
+    for (name, version, extra) in packages {
+        let package = fixture.package(name, version, &[("run.sh", "echo hello\n")], extra.clone());
+        let target = dir.join(format!("{}/{}-{}.ebpm.zip", name, name, version));
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+        fs::copy(&package.path, target).unwrap();
+    }
+    Repository::open(dir.to_str().unwrap())
+        .generate_index()
+        .unwrap();
+    dir
+}
+
+fn request(fixture: &Fixture, names: &[&str]) -> (Vec<String>, Vec<String>) {
+    let names: Vec<String> = names.iter().map(|it| it.to_string()).collect();
+    let (packages, dependencies) = repository::collect(&fixture.ebpm, &names).unwrap();
+    let describe = |packages: Vec<ebpm::package::Package>| {
+        packages
+            .iter()
+            .map(|it| {
+                let manifest = it.manifest().unwrap();
+                format!("{} {}", manifest.name, manifest.version)
+            })
+            .collect()
+    };
+    (describe(packages), describe(dependencies))
+}
+
+#[test]
+fn index_lists_every_package() {
+    let fixture = Fixture::new();
+    let dir = repository(
+        &fixture,
+        &[
+            ("app", "1.0.0", json!({ "description": "An app" })),
+            ("lib", "1.0.0", json!({})),
+        ],
+    );
+
+    let index = Repository::open(&format!("file://{}", dir.display()))
+        .index()
+        .unwrap();
+    let entries: Vec<(&str, &str)> = index
+        .packages
+        .iter()
+        .map(|it| (it.name.as_str(), it.path.as_str()))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("app", "app/app-1.0.0.ebpm.zip"),
+            ("lib", "lib/lib-1.0.0.ebpm.zip")
+        ]
+    );
+    assert_eq!(index.packages[0].description, "An app");
+}
+
+#[test]
+fn search_matches_names_and_descriptions() {
+    let fixture = Fixture::new();
+    let dir = repository(
+        &fixture,
+        &[
+            ("app", "1.0.0", json!({ "description": "Edits Text" })),
+            ("textutil", "1.0.0", json!({})),
+            ("lib", "1.0.0", json!({})),
+        ],
+    );
+    Repository::add(&fixture.ebpm, dir.to_str().unwrap()).unwrap();
+
+    let found: Vec<String> = repository::search(&fixture.ebpm, "TEXT")
+        .into_iter()
+        .map(|it| it.name)
+        .collect();
+    assert_eq!(found, ["app", "textutil"]);
+    assert!(repository::search(&fixture.ebpm, "nothing").is_empty());
+}
+
+#[test]
+fn install_by_name_pulls_in_dependencies() {
+    let fixture = Fixture::new();
+    let dir = repository(
+        &fixture,
+        &[
+            ("app", "1.0.0", json!({})),
+            ("app", "1.1.0", json!({ "dependencies": { "lib": "^1" } })),
+            ("app", "2.0.0", json!({})),
+            ("lib", "1.0.0", json!({})),
+            ("lib", "1.4.0", json!({})),
+        ],
+    );
+    Repository::add(&fixture.ebpm, dir.to_str().unwrap()).unwrap();
+
+    assert_eq!(
+        request(&fixture, &["app@1"]),
+        (vec!["app 1.1.0".into()], vec!["lib 1.4.0".into()])
+    );
+    assert_eq!(request(&fixture, &["app"]).0, ["app 2.0.0"]);
+
+    let names = ["app@1".to_string()];
+    let (packages, dependencies) = repository::collect(&fixture.ebpm, &names).unwrap();
+    fixture
+        .ebpm
+        .install(&packages, &dependencies, false, false)
+        .unwrap();
+    assert!(fixture.ebpm.program("lib").unwrap().auto_installed);
+    // Installed dependencies are not pulled in again.
+    assert_eq!(request(&fixture, &["app@1"]).1, Vec::<String>::new());
+}
+
+#[test]
+fn unknown_and_stale_packages_are_refused() {
+    let fixture = Fixture::new();
+    let dir = repository(&fixture, &[("app", "1.0.0", json!({}))]);
+    Repository::add(&fixture.ebpm, dir.to_str().unwrap()).unwrap();
+
+    let names = ["other".to_string()];
+    let err = repository::collect(&fixture.ebpm, &names).err().unwrap();
+    assert!(matches!(err, Error::NotFound(_)), "{}", err);
+
+    fs::write(dir.join("app/app-1.0.0.ebpm.zip"), "replaced").unwrap();
+    let names = ["app".to_string()];
+    let err = repository::collect(&fixture.ebpm, &names).err().unwrap();
+    assert!(matches!(err, Error::Integrity { .. }), "{}", err);
+}
@@ -0,0 +1,116 @@
+mod common;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use common::Fixture;
+use ebpm::{
+    error::Error,
+    event::Event,
+    package::Package,
+    signature::{self, Policy},
+};
+use serde_json::json;
+
+/// Generates a key pair in the fixture directory, returning the secret and
+/// public key files.
+fn keys(fixture: &Fixture, name: &str) -> (PathBuf, PathBuf) {
+    let (secret, public) =
+        signature::generate(fixture.dir.path().join(name).to_str().unwrap()).unwrap();
+    (secret.into(), public.into())
+}
+
+fn package(fixture: &Fixture) -> Package {
+    fixture.package("hello", "1.0.0", &[("run.sh", "echo hello\n")], json!({}))
+}
+
+fn refused(fixture: &Fixture, package: Package) {
+    let err = fixture
+        .ebpm
+        .install(&[package], &[], false, false)
+        .unwrap_err();
+    assert!(matches!(err, Error::Integrity { .. }), "{}", err);
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+}
+
+fn sign(package: &Package, secret: &Path) {
+    signature::sign(&package.path, secret).unwrap();
+}
+
+#[test]
+fn signed_package_is_verified() {
+    let mut fixture = Fixture::new();
+    let verified = Arc::new(Mutex::new(Vec::new()));
+    let reported = verified.clone();
+    fixture.ebpm.set_reporter(move |event| {
+        if let Event::SignatureVerified { key, .. } = event {
+            reported.lock().unwrap().push(key.clone());
+        }
+    });
+    let (secret, public) = keys(&fixture, "release");
+    signature::add(&fixture.ebpm, "release", &public).unwrap();
+    Policy::Require.save(&fixture.ebpm).unwrap();
+
+    let package = package(&fixture);
+    sign(&package, &secret);
+    fixture.install(&package);
+    assert_eq!(*verified.lock().unwrap(), ["release"]);
+}
+
+#[test]
+fn untrusted_or_broken_signatures_are_refused() {
+    let fixture = Fixture::new();
+    let (secret, public) = keys(&fixture, "release");
+    let (other, _) = keys(&fixture, "other");
+    signature::add(&fixture.ebpm, "release", &public).unwrap();
+
+    let package = package(&fixture);
+    sign(&package, &other);
+    refused(&fixture, Package::new(package.path.clone()));
+
+    // A package changed after signing no longer matches its signature.
+    sign(&package, &secret);
+    let mut content = fs::read(&package.path).unwrap();
+    content.extend_from_slice(b"appended");
+    fs::write(&package.path, content).unwrap();
+    refused(&fixture, package);
+}
+
+#[test]
+fn policy_decides_about_unsigned_packages() {
+    let fixture = Fixture::new();
+    assert!(matches!(Policy::load(&fixture.ebpm), Policy::Warn));
+
+    Policy::Require.save(&fixture.ebpm).unwrap();
+    assert!(matches!(Policy::load(&fixture.ebpm), Policy::Require));
+    refused(&fixture, package(&fixture));
+
+    Policy::Warn.save(&fixture.ebpm).unwrap();
+    fixture.install(&package(&fixture));
+}
+
+#[test]
+fn trusted_keys_are_managed_by_name() {
+    let fixture = Fixture::new();
+    let (_, public) = keys(&fixture, "release");
+    signature::add(&fixture.ebpm, "release", &public).unwrap();
+
+    let err = signature::add(&fixture.ebpm, "release", &public).unwrap_err();
+    assert!(matches!(err, Error::Conflict(_)), "{}", err);
+    for name in ["", "../release", ".hidden"] {
+        let err = signature::add(&fixture.ebpm, name, &public).unwrap_err();
+        assert!(matches!(err, Error::Usage(_)), "{}", err);
+    }
+
+    let names: Vec<String> = signature::list(&fixture.ebpm)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, ["release"]);
+    signature::remove(&fixture.ebpm, "release").unwrap();
+    assert!(signature::list(&fixture.ebpm).unwrap().is_empty());
+}
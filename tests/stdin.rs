@@ -0,0 +1,87 @@
+mod common;
+
+use std::{
+    fs::File,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use common::Fixture;
+use ebpm::{
+    error::Error,
+    package::Package,
+    signature::{self, Policy},
+};
+use serde_json::json;
+
+fn package(fixture: &Fixture) -> Package {
+    fixture.package("hello", "1.0.0", &[("run.sh", "echo hello\n")], json!({}))
+}
+
+/// Runs the `ebpm` binary on the fixture root with `stdin` piped in.
+fn ebpm(fixture: &Fixture, args: &[&str], stdin: &Path) -> bool {
+    Command::new(env!("CARGO_BIN_EXE_ebpm"))
+        .args(args)
+        .env("HOME", fixture.dir.path())
+        .env("EBPM_HOME", fixture.ebpm.root())
+        .stdin(File::open(stdin).unwrap())
+        .stdout(Stdio::null())
+        .status()
+        .unwrap()
+        .success()
+}
+
+#[test]
+fn package_is_read_from_a_reader() {
+    let fixture = Fixture::new();
+    let built = package(&fixture);
+    let package = Package::from_reader(&fixture.ebpm, File::open(&built.path).unwrap()).unwrap();
+    let buffer = package.path.clone();
+    assert_ne!(buffer, built.path);
+
+    fixture.install(&package);
+    assert_eq!(fixture.read("hello", "run.sh"), "echo hello\n");
+    drop(package);
+    assert!(!buffer.exists());
+}
+
+#[test]
+fn signature_is_given_separately() {
+    let fixture = Fixture::new();
+    let (secret, public) =
+        signature::generate(fixture.dir.path().join("release").to_str().unwrap()).unwrap();
+    signature::add(&fixture.ebpm, "release", Path::new(&public)).unwrap();
+    Policy::Require.save(&fixture.ebpm).unwrap();
+    let built = package(&fixture);
+    signature::sign(&built.path, Path::new(&secret)).unwrap();
+    let signature = signature::signature_path(&built.path);
+
+    let read = || Package::from_reader(&fixture.ebpm, File::open(&built.path).unwrap()).unwrap();
+    let err = fixture
+        .ebpm
+        .install(&[read()], &[], false, false)
+        .unwrap_err();
+    assert!(matches!(err, Error::Integrity { .. }), "{}", err);
+
+    fixture.install(&read().with_signature(&signature).unwrap());
+    assert_eq!(fixture.ebpm.list().unwrap().len(), 1);
+
+    // Packages given by path have their signature next to them.
+    let Err(err) = Package::new(built.path.clone()).with_signature(&signature) else {
+        panic!("a signature was given for a package file");
+    };
+    assert!(matches!(err, Error::Usage(_)), "{}", err);
+}
+
+#[test]
+fn cli_installs_from_stdin() {
+    let fixture = Fixture::new();
+    let built = package(&fixture);
+
+    assert!(ebpm(&fixture, &["install", "-"], &built.path));
+    assert_eq!(fixture.read("hello", "run.sh"), "echo hello\n");
+
+    let upgrade = fixture.package("hello", "1.1.0", &[("run.sh", "echo v2\n")], json!({}));
+    assert!(ebpm(&fixture, &["upgrade", "-"], &upgrade.path));
+    assert_eq!(fixture.read("hello", "run.sh"), "echo v2\n");
+}
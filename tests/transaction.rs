@@ -0,0 +1,56 @@
+mod common;
+
+use std::{fs, sync::atomic::Ordering};
+
+use common::Fixture;
+use ebpm::error::Error;
+use serde_json::json;
+
+fn version(fixture: &Fixture, name: &str) -> String {
+    fixture.ebpm.program(name).unwrap().version.to_string()
+}
+
+fn left(fixture: &Fixture) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(fixture.ebpm.root().join("res"))
+        .unwrap()
+        .map(|it| it.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn failing_install_script_keeps_the_previous_version() {
+    let fixture = Fixture::new();
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+
+    let files = [("run.sh", "v2\n"), ("fail.sh", "exit 1\n")];
+    let package = fixture.package(
+        "app",
+        "2.0.0",
+        &files,
+        json!({ "install_script": "fail.sh" }),
+    );
+    let err = fixture.ebpm.upgrade(&package, false, false).unwrap_err();
+    assert!(matches!(err, Error::Script { .. }), "{}", err);
+
+    assert_eq!(version(&fixture, "app"), "1.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v1\n");
+    assert!(fixture.ebpm.verify("app").unwrap().is_empty());
+    assert_eq!(left(&fixture), ["app@1.0.0"]);
+}
+
+#[test]
+fn interrupted_upgrade_is_rolled_back() {
+    let fixture = Fixture::new();
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+
+    let package = fixture.package("app", "2.0.0", &[("run.sh", "v2\n")], json!({}));
+    fixture.ebpm.cancellation().store(true, Ordering::SeqCst);
+    let err = fixture.ebpm.upgrade(&package, false, false).unwrap_err();
+    assert!(matches!(err, Error::Interrupted), "{}", err);
+
+    assert_eq!(version(&fixture, "app"), "1.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v1\n");
+    assert_eq!(left(&fixture), ["app@1.0.0"]);
+}
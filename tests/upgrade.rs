@@ -0,0 +1,48 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use ebpm::error::Error;
+use serde_json::json;
+
+fn version(fixture: &Fixture, name: &str) -> String {
+    fixture.ebpm.program(name).unwrap().version.to_string()
+}
+
+#[test]
+fn upgrade_installs_newer_versions_only() {
+    let fixture = Fixture::new();
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+    let newer = fixture.package("app", "1.2.0", &[("run.sh", "v2\n")], json!({}));
+    fixture.ebpm.upgrade(&newer, false, false).unwrap();
+    assert_eq!(version(&fixture, "app"), "1.2.0");
+
+    // The same version is skipped, an older one refused.
+    fixture.ebpm.upgrade(&newer, false, false).unwrap();
+    assert_eq!(fixture.read("app", "run.sh"), "v2\n");
+    let older = fixture.package("app", "1.1.0", &[("run.sh", "v3\n")], json!({}));
+    let err = fixture.ebpm.upgrade(&older, false, false).unwrap_err();
+    assert!(matches!(err, Error::Conflict(_)), "{}", err);
+    assert_eq!(version(&fixture, "app"), "1.2.0");
+
+    fixture.ebpm.upgrade(&older, true, false).unwrap();
+    assert_eq!(version(&fixture, "app"), "1.1.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v3\n");
+}
+
+#[test]
+fn manifest_without_version_is_refused() {
+    let fixture = Fixture::new();
+    let dir = fixture.source("app", "1.0.0", &[("run.sh", "v1\n")], json!({}));
+    let manifest = dir.join("manifest.ebpm.json");
+    let mut json: serde_json::Value =
+        serde_json::from_slice(&fs::read(&manifest).unwrap()).unwrap();
+    json.as_object_mut().unwrap().remove("version");
+    fs::write(&manifest, json.to_string()).unwrap();
+
+    let Err(err) = fixture.ebpm.build(&dir, &Default::default()) else {
+        panic!("a manifest without version was accepted");
+    };
+    assert!(matches!(err, Error::Manifest { .. }), "{}", err);
+}
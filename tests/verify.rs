@@ -0,0 +1,63 @@
+mod common;
+
+use std::{fs, os::unix::fs::PermissionsExt};
+
+use common::Fixture;
+use ebpm::database::Difference;
+use serde_json::json;
+
+const FILES: &[(&str, &str)] = &[
+    ("run.sh", "echo hello\n"),
+    ("lib/a.txt", "a\n"),
+    ("lib/b.txt", "b\n"),
+];
+
+fn installed(fixture: &Fixture) {
+    let extra = json!({ "files": ["run.sh", "lib"] });
+    fixture.install(&fixture.package("hello", "1.0.0", FILES, extra));
+}
+
+fn describe(differences: &[Difference]) -> Vec<String> {
+    differences.iter().map(ToString::to_string).collect()
+}
+
+#[test]
+fn verify_reports_modified_missing_and_unexpected_files() {
+    let fixture = Fixture::new();
+    installed(&fixture);
+    assert!(fixture.ebpm.verify("hello").unwrap().is_empty());
+
+    let res = fixture.res("hello");
+    fs::write(res.join("run.sh"), "echo changed\n").unwrap();
+    fs::remove_file(res.join("lib/a.txt")).unwrap();
+    fs::set_permissions(res.join("lib/b.txt"), fs::Permissions::from_mode(0o600)).unwrap();
+    fs::write(res.join("lib/new.txt"), "new\n").unwrap();
+
+    let differences = fixture.ebpm.verify("hello").unwrap();
+    let paths: Vec<&str> = differences.iter().map(|it| it.path()).collect();
+    assert_eq!(paths, ["lib/a.txt", "lib/b.txt", "lib/new.txt", "run.sh"]);
+    assert!(matches!(differences[0], Difference::Missing(_)));
+    assert!(matches!(differences[1], Difference::Modified { .. }));
+    assert!(matches!(differences[2], Difference::Unexpected(_)));
+    assert!(matches!(differences[3], Difference::Modified { .. }));
+}
+
+#[test]
+fn repair_restores_files_from_the_cache() {
+    let fixture = Fixture::new();
+    installed(&fixture);
+    let res = fixture.res("hello");
+    fs::write(res.join("run.sh"), "echo changed\n").unwrap();
+    fs::remove_dir_all(res.join("lib")).unwrap();
+    fs::write(res.join("new.txt"), "new\n").unwrap();
+
+    // Unexpected files are only reported.
+    let remaining = fixture.ebpm.repair("hello").unwrap();
+    assert_eq!(describe(&remaining), ["unexpected new.txt"]);
+    assert_eq!(fixture.read("hello", "run.sh"), "echo hello\n");
+    assert_eq!(fixture.read("hello", "lib/a.txt"), "a\n");
+    assert_eq!(
+        describe(&fixture.ebpm.verify("hello").unwrap()),
+        ["unexpected new.txt"]
+    );
+}
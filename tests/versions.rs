@@ -0,0 +1,89 @@
+mod common;
+
+use common::Fixture;
+use ebpm::error::Error;
+use semver::Version;
+use serde_json::json;
+
+fn version(fixture: &Fixture, name: &str) -> String {
+    fixture.ebpm.program(name).unwrap().version.to_string()
+}
+
+/// Installs `app` 1.0.0 and keeps it next to 2.0.0, which becomes active.
+fn side_by_side(fixture: &Fixture) {
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+    let package = fixture.package("app", "2.0.0", &[("run.sh", "v2\n")], json!({}));
+    fixture.ebpm.upgrade(&package, false, true).unwrap();
+}
+
+#[test]
+fn switch_between_kept_versions() {
+    let fixture = Fixture::new();
+    side_by_side(&fixture);
+    assert_eq!(fixture.ebpm.list().unwrap().len(), 2);
+    assert_eq!(version(&fixture, "app"), "2.0.0");
+
+    fixture.ebpm.switch("app", &Version::new(1, 0, 0)).unwrap();
+    assert_eq!(version(&fixture, "app"), "1.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v1\n");
+
+    let err = fixture
+        .ebpm
+        .switch("app", &Version::new(3, 0, 0))
+        .unwrap_err();
+    assert!(matches!(err, Error::NotFound(_)), "{}", err);
+}
+
+#[test]
+fn versions_are_removed_one_by_one() {
+    let fixture = Fixture::new();
+    side_by_side(&fixture);
+
+    let err = fixture.ebpm.remove("app@2.0.0").unwrap_err();
+    assert!(matches!(err, Error::Conflict(_)), "{}", err);
+    let err = fixture.ebpm.remove("app@3.0.0").unwrap_err();
+    assert!(matches!(err, Error::NotFound(_)), "{}", err);
+
+    fixture.ebpm.remove("app@1.0.0").unwrap();
+    assert_eq!(fixture.ebpm.list().unwrap().len(), 1);
+    assert_eq!(version(&fixture, "app"), "2.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v2\n");
+
+    fixture.ebpm.remove("app").unwrap();
+    assert!(fixture.ebpm.list().unwrap().is_empty());
+    assert!(!fixture.ebpm.resources("app").exe_path.exists());
+}
+
+#[test]
+fn rollback_restores_the_replaced_version() {
+    let fixture = Fixture::new();
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+    let package = fixture.package("app", "2.0.0", &[("run.sh", "v2\n")], json!({}));
+    fixture.ebpm.upgrade(&package, false, false).unwrap();
+    assert_eq!(fixture.ebpm.list().unwrap().len(), 1);
+
+    let restored = fixture.ebpm.rollback("app").unwrap();
+    assert_eq!(restored.version.to_string(), "1.0.0");
+    assert_eq!(version(&fixture, "app"), "1.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v1\n");
+    assert!(fixture.ebpm.verify("app").unwrap().is_empty());
+
+    // The version rolled back from is kept in turn.
+    fixture.ebpm.rollback("app").unwrap();
+    assert_eq!(version(&fixture, "app"), "2.0.0");
+    assert_eq!(fixture.read("app", "run.sh"), "v2\n");
+}
+
+#[test]
+fn rollback_needs_a_replaced_version() {
+    let fixture = Fixture::new();
+    fixture.install(&fixture.package("app", "1.0.0", &[("run.sh", "v1\n")], json!({})));
+    let err = fixture.ebpm.rollback("app").err().unwrap();
+    assert!(matches!(err, Error::NotFound(_)), "{}", err);
+
+    // Versions kept side by side are switched to instead.
+    let package = fixture.package("app", "2.0.0", &[("run.sh", "v2\n")], json!({}));
+    fixture.ebpm.upgrade(&package, false, true).unwrap();
+    let err = fixture.ebpm.rollback("app").err().unwrap();
+    assert!(matches!(err, Error::NotFound(_)), "{}", err);
+}